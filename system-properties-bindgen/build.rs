@@ -5,11 +5,15 @@ fn main() {
 
     let bindings = bindgen::Builder::default()
         .header("bindgen/system_properties.h")
+        .allowlist_function("__system_property_area_serial")
         .allowlist_function("__system_property_find")
         .allowlist_function("__system_property_foreach")
         .allowlist_function("__system_property_read_callback")
+        .allowlist_function("__system_property_serial")
         .allowlist_function("__system_property_set")
         .allowlist_function("__system_property_wait")
+        .allowlist_var("PROP_NAME_MAX")
+        .allowlist_var("PROP_VALUE_MAX")
         .blocklist_type("timespec")
         .raw_line("use libc::timespec;")
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
@@ -16,34 +16,273 @@
 //! in Android system properties.
 
 // Temporary public re-export to avoid breaking dependents.
+use self::error::SysPropError;
 pub use self::error::{PropertyWatcherError, Result};
-use anyhow::Context;
 use libc::timespec;
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(feature = "tokio")]
+use std::future::Future;
 use std::os::raw::c_char;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
 use std::ptr::null;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+use std::sync::OnceLock;
+#[cfg(feature = "tokio")]
+use std::task::{Context as TaskContext, Poll};
 use std::{
-    ffi::{c_uint, c_void, CStr, CString},
+    ffi::{c_uint, c_void, CStr, CString, NulError},
+    fmt,
     time::{Duration, Instant},
 };
-use system_properties_bindgen::prop_info as PropInfo;
+/// Bionic's opaque handle to a system property. See [`PropertyWatcher::raw_prop_info`].
+pub use sys::prop_info as PropInfo;
 
+mod area_watcher;
+#[cfg(feature = "serde")]
+mod de;
 pub mod error;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "test-mock")]
+pub mod mock;
+mod multi_watcher;
+#[cfg(feature = "observability")]
+pub mod observer;
 #[doc(hidden)]
 pub mod parsers_formatters;
+#[cfg(feature = "ro-cache")]
+pub mod ro_cache;
+mod scoped_property;
+mod subscription;
+mod sys;
+
+pub use self::area_watcher::{AreaChanges, AreaWatcher};
+#[cfg(feature = "serde")]
+pub use self::de::deserialize_from_props;
+pub use self::multi_watcher::MultiWatcher;
+pub use self::scoped_property::ScopedProperty;
+pub use self::subscription::{subscribe, Subscription};
+
+/// How long each blocking `__system_property_wait` call made by
+/// [`PropertyWatcher::wait_async`] may last before checking whether the wait was cancelled.
+#[cfg(feature = "tokio")]
+const ASYNC_WAIT_SLICE: Duration = Duration::from_millis(100);
+
+/// How long each blocking `__system_property_wait` call made by
+/// [`PropertyWatcher::wait_cancellable`] may last before checking whether the wait was cancelled.
+///
+/// Shorter intervals make cancellation more responsive at the cost of waking up more often.
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long [`write_verified`] waits for a written value to become visible before reading it back.
+pub const WRITE_VERIFY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Maximum size of a property value in bytes, including the NUL terminator.
+///
+/// Only `ro.` properties can have longer values.
+pub const PROP_VALUE_MAX: usize = system_properties_bindgen::PROP_VALUE_MAX as usize;
+
+/// Maximum size of a property name in bytes, including the NUL terminator, on devices older than
+/// API level 26. Newer devices don't limit the length of property names. See [`max_name_len`].
+pub const PROP_NAME_MAX: usize = system_properties_bindgen::PROP_NAME_MAX as usize;
+
+/// The first API level that doesn't limit the length of property names.
+const UNLIMITED_NAME_SDK: u32 = 26;
 
 /// PropertyWatcher takes the name of an Android system property such
 /// as `keystore.boot_level`; it can report the current value of this
 /// property, or wait for it to change.
+///
+/// A PropertyWatcher is `Send`, so it can be moved into another thread or
+/// put behind an `Arc<Mutex<_>>`. The `__system_property_*` functions are
+/// thread-safe and the resolved property handle stays valid for the
+/// lifetime of the process, so nothing ties a watcher to the thread that
+/// created it. It is also `Sync`, but that is of limited use since every
+/// operation that reads or waits takes `&mut self` to update the cached
+/// handle and serial number.
 pub struct PropertyWatcher {
     prop_name: CString,
     prop_info: Option<&'static PropInfo>,
     serial: c_uint,
+    default_timeout: Option<Duration>,
+    bound_creation_wait: bool,
+    empty_is_absent: bool,
+    // Value reported by the last wait_for_delta, or None if it hasn't been called yet. The inner
+    // None means that the property didn't exist.
+    last_value: Option<Option<String>>,
+}
+
+impl fmt::Debug for PropertyWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't print the prop_info pointer, only whether it has been resolved.
+        f.debug_struct("PropertyWatcher")
+            .field("name", &self.prop_name.to_string_lossy())
+            .field("resolved", &self.prop_info.is_some())
+            .field("serial", &self.serial)
+            .field("default_timeout", &self.default_timeout)
+            .field("bound_creation_wait", &self.bound_creation_wait)
+            .field("empty_is_absent", &self.empty_is_absent)
+            .finish()
+    }
+}
+
+/// Builder for a [`PropertyWatcher`] with non-default wait behavior.
+#[derive(Clone, Debug)]
+pub struct PropertyWatcherBuilder {
+    name: String,
+    default_timeout: Option<Duration>,
+    bound_creation_wait: bool,
+    empty_is_absent: bool,
+}
+
+impl Default for PropertyWatcherBuilder {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            default_timeout: None,
+            bound_creation_wait: true,
+            empty_is_absent: false,
+        }
+    }
+}
+
+impl PropertyWatcherBuilder {
+    /// Create a builder with the default settings: no default timeout and timeouts honored while
+    /// waiting for the property to be created.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the system property to watch.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Set the timeout used by the wait functions when they are called without one.
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Set whether [`PropertyWatcher::wait`] honors its timeout while waiting for a property
+    /// that doesn't exist yet to be created. This is enabled by default. If disabled, waiting
+    /// for the property to be created is unbounded.
+    pub fn bound_creation_wait(mut self, bound: bool) -> Self {
+        self.bound_creation_wait = bound;
+        self
+    }
+
+    /// Set whether [`PropertyWatcher::wait_until_absent`] treats a property with an empty value as
+    /// absent. This is disabled by default.
+    pub fn empty_is_absent(mut self, empty_is_absent: bool) -> Self {
+        self.empty_is_absent = empty_is_absent;
+        self
+    }
+
+    /// Create the PropertyWatcher.
+    pub fn build(self) -> Result<PropertyWatcher> {
+        Ok(PropertyWatcher {
+            prop_name: CString::new(self.name).map_err(bad_name_error)?,
+            prop_info: None,
+            serial: 0,
+            default_timeout: self.default_timeout,
+            bound_creation_wait: self.bound_creation_wait,
+            empty_is_absent: self.empty_is_absent,
+            last_value: None,
+        })
+    }
+}
+
+/// The value of a system property before and after a change. See
+/// [`PropertyWatcher::wait_for_delta`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PropertyChange {
+    /// The previous value, or `None` if the property didn't exist.
+    pub old: Option<String>,
+    /// The new value, or `None` if the property doesn't exist.
+    pub new: Option<String>,
 }
 
 impl PropertyWatcher {
     /// Create a PropertyWatcher for the named system property.
     pub fn new(name: &str) -> Result<Self> {
-        Ok(Self { prop_name: CString::new(name)?, prop_info: None, serial: 0 })
+        PropertyWatcherBuilder::new().name(name).build()
+    }
+
+    /// Returns the serial number of the last change observed by this watcher.
+    ///
+    /// This is updated by the wait functions and never triggers a wait itself.
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// Forgets the resolved property and the last observed serial number, so that the next
+    /// operation looks up the property again as if the watcher was just created.
+    ///
+    /// On a device, a property can't be removed once it exists, so this is normally not needed.
+    /// It is useful when the property area is recreated behind the process's back, such as when a
+    /// test harness reinitializes or clears the property store. There is no way to detect this
+    /// automatically because the property API doesn't report whether a resolved property is
+    /// still part of the current property area.
+    pub fn reset(&mut self) {
+        self.prop_info = None;
+        self.serial = 0;
+        self.last_value = None;
+    }
+
+    /// Returns the handle to the system property for use with `__system_property_*` functions
+    /// that this crate doesn't wrap, or `None` if the property doesn't exist.
+    ///
+    /// The property is looked up if it hasn't been yet, and the handle is cached, so this avoids
+    /// calling `__system_property_find` again.
+    ///
+    /// # Safety
+    ///
+    /// The pointer is owned by bionic. It must only be passed to `__system_property_*` functions
+    /// and must never be dereferenced, written through, or freed. It stays valid for the lifetime
+    /// of the process, since bionic never unmaps the property area or moves a property once it
+    /// has been created. Operations done through it, such as waiting for a change, aren't tracked
+    /// by this watcher, so the serial number returned by [`Self::serial`] isn't updated. When the
+    /// `test-mock` store is in use, the pointer refers to the mock store instead and must not be
+    /// passed to bionic at all.
+    pub unsafe fn raw_prop_info(&mut self) -> Option<*const PropInfo> {
+        self.get_prop_info().map(|p| p as *const PropInfo)
+    }
+
+    /// Returns whether the system property exists, without reading its value.
+    ///
+    /// Once the property has been found, this doesn't look it up again, since a property can't
+    /// be removed on a device. See [`Self::reset`].
+    pub fn exists(&mut self) -> bool {
+        self.get_prop_info().is_some()
+    }
+
+    /// Returns the name of the system property.
+    pub fn name(&self) -> &str {
+        // The name was created from a &str, so it's always valid UTF-8.
+        self.prop_name.to_str().expect("property name is not UTF-8")
+    }
+
+    /// Returns whether the system property is read-only. See [`is_read_only`].
+    ///
+    /// There is no way to query the SELinux context of a property through the API exposed by
+    /// bionic. `__system_property_find` and `__system_property_read_callback` only give access to
+    /// the name, value, and serial number. The context is only known to init and libselinux,
+    /// which map names to contexts via the `property_contexts` files.
+    pub fn is_read_only(&self) -> bool {
+        is_read_only(self.name())
+    }
+
+    // Reports an access to this property to the observer, if any.
+    #[cfg(feature = "observability")]
+    fn observe(&self, access: fn(&str) -> observer::Access<'_>) {
+        observer::notify(access(self.name()));
     }
 
     // Lazy-initializing accessor for self.prop_info.
@@ -51,22 +290,21 @@ impl PropertyWatcher {
         if self.prop_info.is_none() {
             // SAFETY: Input and output are both const. The returned pointer is valid for the
             // lifetime of the program.
-            self.prop_info = unsafe {
-                system_properties_bindgen::__system_property_find(self.prop_name.as_ptr()).as_ref()
-            };
+            self.prop_info =
+                unsafe { sys::__system_property_find(self.prop_name.as_ptr()).as_ref() };
         }
         self.prop_info
     }
 
-    fn read_raw<F: FnMut(Option<&CStr>, Option<&CStr>)>(prop_info: &PropInfo, mut f: F) {
+    fn read_raw<F: FnMut(Option<&CStr>, Option<&CStr>, c_uint)>(prop_info: &PropInfo, mut f: F) {
         // Unsafe function converts values passed to us by
         // __system_property_read_callback to Rust form
         // and pass them to inner callback.
-        unsafe extern "C" fn callback<F: FnMut(Option<&CStr>, Option<&CStr>)>(
+        unsafe extern "C" fn callback<F: FnMut(Option<&CStr>, Option<&CStr>, c_uint)>(
             res_p: *mut c_void,
             name: *const c_char,
             value: *const c_char,
-            _: c_uint,
+            serial: c_uint,
         ) {
             let name = if name.is_null() {
                 None
@@ -85,12 +323,12 @@ impl PropertyWatcher {
             // SAFETY: We converted the FnMut from `F` to a void pointer below, now we convert it
             // back.
             let f = unsafe { &mut *res_p.cast::<F>() };
-            f(name, value);
+            f(name, value, serial);
         }
 
         // SAFETY: We convert the FnMut to a void pointer, and unwrap it in our callback.
         unsafe {
-            system_properties_bindgen::__system_property_read_callback(
+            sys::__system_property_read_callback(
                 prop_info,
                 Some(callback::<F>),
                 &mut f as *mut F as *mut c_void,
@@ -101,14 +339,19 @@ impl PropertyWatcher {
     /// Call the passed function, passing it the name and current value
     /// of this system property. See documentation for
     /// `__system_property_read_callback` for details.
-    /// Returns an error if the property is empty or doesn't exist.
+    /// Returns an error if the property doesn't exist. A property that
+    /// exists with an empty value is passed to `f` as an empty string.
     pub fn read<T, F>(&mut self, mut f: F) -> Result<T>
     where
         F: FnMut(&str, &str) -> anyhow::Result<T>,
     {
-        let prop_info = self.get_prop_info().ok_or(PropertyWatcherError::SystemPropertyAbsent)?;
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Read { name });
+        let prop_info = self.get_prop_info().ok_or_else(|| {
+            PropertyWatcherError::SystemPropertyAbsent { name: self.name().to_owned() }
+        })?;
         let mut result = Err(PropertyWatcherError::ReadCallbackNotCalled);
-        Self::read_raw(prop_info, |name, value| {
+        Self::read_raw(prop_info, |name, value, _serial| {
             // use a wrapping closure as an erzatz try block.
             result = (|| {
                 let name = name.ok_or(PropertyWatcherError::MissingCString)?.to_str()?;
@@ -119,6 +362,107 @@ impl PropertyWatcher {
         result
     }
 
+    /// Call the passed function, passing it the name and current value of this system property,
+    /// and return its result.
+    ///
+    /// This is like [`Self::read`], but for functions that can't fail, so they don't need to
+    /// return an `anyhow::Result`. Returns an error if the property doesn't exist.
+    pub fn read_ok<T, F>(&mut self, mut f: F) -> Result<T>
+    where
+        F: FnMut(&str, &str) -> T,
+    {
+        self.read(|name, value| Ok(f(name, value)))
+    }
+
+    /// Call the passed function with the current value of this system property and return its
+    /// result.
+    ///
+    /// This is a simpler alternative to [`Self::read`] for infallible transformations, such as
+    /// hashing or comparing the value without copying it. Returns an error if the property
+    /// doesn't exist.
+    pub fn read_map<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&str) -> T,
+    {
+        let mut f = Some(f);
+        let mut result = None;
+        self.read(|_name, value| {
+            result = f.take().map(|f| f(value));
+            Ok(())
+        })?;
+        result.ok_or(PropertyWatcherError::ReadCallbackNotCalled)
+    }
+
+    // Reads the value and serial number of the property, or returns None if it doesn't exist.
+    fn read_with_serial(&mut self) -> Result<Option<(String, u32)>> {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Read { name });
+        let Some(prop_info) = self.get_prop_info() else {
+            return Ok(None);
+        };
+        let mut result = Err(PropertyWatcherError::ReadCallbackNotCalled);
+        Self::read_raw(prop_info, |_name, value, serial| {
+            // use a wrapping closure as an erzatz try block.
+            result = (|| {
+                let value = value.ok_or(PropertyWatcherError::MissingCString)?.to_str()?;
+                Ok(Some((value.to_owned(), serial)))
+            })()
+        });
+        result
+    }
+
+    // Reads the value of the property, or returns None if it doesn't exist, and records its
+    // serial number so that the next wait only returns for later changes.
+    fn read_and_record_serial(&mut self) -> Result<Option<String>> {
+        let current = self.read_with_serial()?;
+        if let Some((_, serial)) = &current {
+            self.serial = *serial;
+        }
+        Ok(current.map(|(value, _)| value))
+    }
+
+    /// Returns the current value of this system property.
+    ///
+    /// The property is only looked up the first time, so reusing a PropertyWatcher is the
+    /// efficient way to read the same property repeatedly.
+    /// Returns an error if the property doesn't exist.
+    pub fn current(&mut self) -> Result<String> {
+        self.read(|_, value| Ok(value.to_owned()))
+    }
+
+    /// Call the passed function, passing it the raw bytes of the name and
+    /// current value of this system property. Unlike [`Self::read`], the
+    /// name and value are not required to be valid UTF-8.
+    /// Returns an error if the property doesn't exist.
+    pub fn read_raw_bytes<T, F>(&mut self, mut f: F) -> Result<T>
+    where
+        F: FnMut(&[u8], &[u8]) -> anyhow::Result<T>,
+    {
+        self.read_cstr(|name, value| f(name.to_bytes(), value.to_bytes()))
+    }
+
+    // Like read_raw_bytes, but passes the name and value as C strings.
+    fn read_cstr<T, F>(&mut self, mut f: F) -> Result<T>
+    where
+        F: FnMut(&CStr, &CStr) -> anyhow::Result<T>,
+    {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Read { name });
+        let prop_info = self.get_prop_info().ok_or_else(|| {
+            PropertyWatcherError::SystemPropertyAbsent { name: self.name().to_owned() }
+        })?;
+        let mut result = Err(PropertyWatcherError::ReadCallbackNotCalled);
+        Self::read_raw(prop_info, |name, value, _serial| {
+            // use a wrapping closure as an erzatz try block.
+            result = (|| {
+                let name = name.ok_or(PropertyWatcherError::MissingCString)?;
+                let value = value.ok_or(PropertyWatcherError::MissingCString)?;
+                f(name, value).map_err(PropertyWatcherError::CallbackError)
+            })()
+        });
+        result
+    }
+
     // Waits for the property that self is watching to be created. Returns immediately if the
     // property already exists.
     fn wait_for_property_creation_until(&mut self, until: Option<Instant>) -> Result<()> {
@@ -132,7 +476,7 @@ impl PropertyWatcher {
                     if !unsafe {
                         // Wait for a global serial number change, then try again. On success,
                         // the function will update global_serial with the last version seen.
-                        system_properties_bindgen::__system_property_wait(
+                        sys::__system_property_wait(
                             null(),
                             global_serial,
                             &mut global_serial,
@@ -143,7 +487,7 @@ impl PropertyWatcher {
                             },
                         )
                     } {
-                        return Err(PropertyWatcherError::WaitFailed);
+                        return Err(wait_error(until, self.name().to_owned()));
                     }
                 }
             }
@@ -154,10 +498,12 @@ impl PropertyWatcher {
     ///
     /// This records the serial number of the last change, so race conditions are avoided.
     fn wait_for_property_change_until(&mut self, until: Option<Instant>) -> Result<()> {
-        // If the property is None, then wait for it to be created. Subsequent waits will
-        // skip this step and wait for our specific property to change.
+        // If the property is None, then wait for it to be created, honoring the deadline unless
+        // configured otherwise. Subsequent waits will skip this step and wait for our specific
+        // property to change.
         if self.prop_info.is_none() {
-            return self.wait_for_property_creation_until(None);
+            let until = if self.bound_creation_wait { until } else { None };
+            return self.wait_for_property_creation_until(until);
         }
 
         let remaining_timeout = remaining_time_until(until);
@@ -165,7 +511,7 @@ impl PropertyWatcher {
         // SAFETY: All arguments are private to PropertyWatcher so we can be confident they are
         // valid.
         if !unsafe {
-            system_properties_bindgen::__system_property_wait(
+            sys::__system_property_wait(
                 match self.prop_info {
                     Some(p) => p,
                     None => null(),
@@ -179,115 +525,1164 @@ impl PropertyWatcher {
                 },
             )
         } {
-            return Err(PropertyWatcherError::WaitFailed);
+            return Err(wait_error(until, self.name().to_owned()));
         }
         self.serial = new_serial;
         Ok(())
     }
 
+    /// Returns whether the system property changed since the last observed change, without
+    /// blocking.
+    ///
+    /// If the serial number of the property differs from the one returned by [`Self::serial`],
+    /// it is recorded and `true` is returned, like a [`Self::wait`] that completed immediately.
+    /// Returns `false` if the property doesn't exist. This is meant for integrating with an
+    /// external event loop that polls periodically.
+    pub fn poll(&mut self) -> Result<bool> {
+        let Some(prop_info) = self.get_prop_info() else {
+            return Ok(false);
+        };
+        // SAFETY: prop_info was returned by __system_property_find and is valid for the lifetime
+        // of the program. The function only reads the property's serial number.
+        let serial = unsafe { sys::__system_property_serial(prop_info) };
+        if serial == self.serial {
+            return Ok(false);
+        }
+        self.serial = serial;
+        Ok(true)
+    }
+
     /// Waits for the system property to change, or the timeout to elapse.
     ///
     /// This records the serial number of the last change, so race conditions are avoided.
+    /// Returns [`PropertyWatcherError::Timeout`] if the timeout elapses first. If `timeout` is
+    /// `None`, the default timeout set via [`PropertyWatcherBuilder`] is used, if any.
     pub fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
-        let until = timeout.map(|timeout| Instant::now() + timeout);
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        let until = timeout.or(self.default_timeout).and_then(deadline_after);
         self.wait_for_property_change_until(until)
     }
 
+    /// Waits for the system property to change, or `deadline` to pass.
+    ///
+    /// This is like [`Self::wait`], but takes an absolute deadline, which is useful when several
+    /// waits share one overall deadline. The default timeout set via [`PropertyWatcherBuilder`]
+    /// is not used.
+    ///
+    /// `__system_property_wait` only accepts a relative timeout, which bionic measures against
+    /// `CLOCK_MONOTONIC`. Before each call, the remaining time is recomputed from `Instant`, which
+    /// also uses `CLOCK_MONOTONIC` on Linux and Android, so both clocks agree and the timeout
+    /// doesn't drift across retries. Returns [`PropertyWatcherError::Timeout`] if the deadline
+    /// passes first.
+    ///
+    /// On success, returns the time remaining until `deadline`, or zero if it has just passed, so
+    /// that it can be passed on as the timeout of the next wait.
+    pub fn wait_until(&mut self, deadline: Instant) -> Result<Duration> {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        self.wait_for_property_change_until(Some(deadline))?;
+        Ok(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Waits for the system property to change, the timeout to elapse, or `cancel` to be set.
+    ///
+    /// Returns [`PropertyWatcherError::Cancelled`] if `cancel` is set before the property changes.
+    /// The flag is checked between bounded waits of 100 ms, so cancellation takes effect within
+    /// that interval. This trades a few extra wakeups for the ability to shut down cleanly while
+    /// waiting for a property that may never change. Otherwise, this behaves like [`Self::wait`].
+    pub fn wait_cancellable(
+        &mut self,
+        timeout: Option<Duration>,
+        cancel: &AtomicBool,
+    ) -> Result<()> {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        let until = timeout.or(self.default_timeout).and_then(deadline_after);
+
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(PropertyWatcherError::Cancelled { name: self.name().to_owned() });
+            }
+
+            // Like in `wait`, waiting for the property to be created may be unbounded, in which
+            // case only the cancellation checks can end the wait early.
+            let creating = self.prop_info.is_none();
+            let until = if creating && !self.bound_creation_wait { None } else { until };
+            let result = if creating {
+                self.wait_for_property_creation_until(earliest(until, CANCEL_CHECK_INTERVAL))
+            } else {
+                self.wait_for_property_change_until(earliest(until, CANCEL_CHECK_INTERVAL))
+            };
+
+            match result {
+                Err(PropertyWatcherError::Timeout { .. })
+                    if until.is_none_or(|until| Instant::now() < until) => {}
+                r => return r,
+            }
+        }
+    }
+
+    /// Waits for the system property to change, or the timeout to elapse, and returns both the
+    /// previous and the new value.
+    ///
+    /// The previous value is the one returned as `new` by the last call, so consecutive calls
+    /// report every change without gaps. On the first call, the previous value is read before
+    /// waiting. Like [`Self::wait_for_change`], the new value is read right after the wait
+    /// completes, so it may reflect a later change than the one that ended the wait. See
+    /// [`Self::wait`] for the timeout behavior.
+    pub fn wait_for_delta(&mut self, timeout: Option<Duration>) -> Result<PropertyChange> {
+        let old = match self.last_value.take() {
+            Some(old) => old,
+            None => self.read_and_record_serial()?,
+        };
+
+        if let Err(e) = self.wait(timeout) {
+            self.last_value = Some(old);
+            return Err(e);
+        }
+
+        // The value may be newer than the change that ended the wait. Recording its serial number
+        // avoids reporting the changes up to it again.
+        let new = self.read_and_record_serial()?;
+        self.last_value = Some(new.clone());
+        Ok(PropertyChange { old, new })
+    }
+
+    /// Waits for the system property to change, or the timeout to elapse, and returns the new
+    /// value.
+    ///
+    /// The value is read right after the wait completes, so it may reflect a later change than
+    /// the one that ended the wait. See [`Self::wait`] for the timeout behavior.
+    pub fn wait_for_change(&mut self, timeout: Option<Duration>) -> Result<String> {
+        self.wait(timeout)?;
+        self.current()
+    }
+
+    /// Waits for the system property to change, or the timeout to elapse, without blocking the
+    /// async runtime.
+    ///
+    /// The blocking `__system_property_wait` calls run on Tokio's blocking thread pool and the
+    /// timeout is driven by Tokio's timer. If the returned future is dropped, the blocking task is
+    /// signalled to stop and exits within a short, bounded amount of time.
+    ///
+    /// Like [`Self::wait`], this records the serial number of the last change, so race conditions
+    /// are avoided. The serial number is only updated if the wait completes. If `timeout` is
+    /// `None`, the default timeout set via [`PropertyWatcherBuilder`] is used, if any.
+    #[cfg(feature = "tokio")]
+    pub async fn wait_async(&mut self, timeout: Option<Duration>) -> Result<()> {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        // Like in `wait`, waiting for the property to be created may be unbounded, in which case
+        // the timeout only applies if the property already exists.
+        let timeout = timeout
+            .or(self.default_timeout)
+            .filter(|_| self.bound_creation_wait || self.get_prop_info().is_some());
+        let cancel = CancelOnDrop::default();
+        let cancelled = cancel.0.clone();
+        let mut watcher = Self {
+            prop_name: self.prop_name.clone(),
+            prop_info: self.prop_info,
+            serial: self.serial,
+            default_timeout: self.default_timeout,
+            bound_creation_wait: self.bound_creation_wait,
+            empty_is_absent: self.empty_is_absent,
+            last_value: None,
+        };
+
+        let task = tokio::task::spawn_blocking(move || {
+            let result = loop {
+                if cancelled.load(Ordering::Relaxed) {
+                    break Err(PropertyWatcherError::WaitFailed {
+                        name: watcher.name().to_owned(),
+                    });
+                }
+
+                // Wait in short slices so that cancellation is noticed promptly.
+                let until = Some(Instant::now() + ASYNC_WAIT_SLICE);
+                let result = if watcher.prop_info.is_none() {
+                    watcher.wait_for_property_creation_until(until)
+                } else {
+                    watcher.wait_for_property_change_until(until)
+                };
+
+                match result {
+                    Err(PropertyWatcherError::Timeout { .. }) => continue,
+                    r => break r,
+                }
+            };
+            (watcher, result)
+        });
+
+        let joined = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, task)
+                .await
+                .map_err(|_| PropertyWatcherError::Timeout { name: self.name().to_owned() })?,
+            None => task.await,
+        };
+        let (watcher, result) = joined
+            .map_err(|_| PropertyWatcherError::WaitFailed { name: self.name().to_owned() })?;
+
+        self.prop_info = watcher.prop_info;
+        self.serial = watcher.serial;
+        result
+    }
+
+    /// Returns a stream that yields the new value of the system property each time it changes.
+    ///
+    /// Each item is produced by [`Self::wait_async`] followed by [`Self::current`], so the first
+    /// item is the current value if this watcher hasn't waited before. Errors are yielded as
+    /// items and the stream continues afterwards, so it never ends on its own. If a default
+    /// timeout was set via [`PropertyWatcherBuilder`], a [`PropertyWatcherError::Timeout`] is
+    /// yielded each time it elapses without a change.
+    ///
+    /// The stream can be dropped at any time. Dropping it cancels the pending blocking wait the
+    /// same way as dropping the future returned by [`Self::wait_async`].
+    #[cfg(feature = "tokio")]
+    pub fn changes(&mut self) -> impl futures_core::Stream<Item = Result<String>> + Send + '_ {
+        Changes { next: Changes::next(self) }
+    }
+
     /// Waits until the property exists and has the given value.
+    ///
+    /// Returns [`PropertyWatcherError::Timeout`] if the timeout elapses first. If `timeout` is
+    /// `None`, the default timeout set via [`PropertyWatcherBuilder`] is used, if any.
     pub fn wait_for_value(
         &mut self,
         expected_value: &str,
         timeout: Option<Duration>,
     ) -> Result<()> {
-        let until = timeout.map(|timeout| Instant::now() + timeout);
+        self.wait_for_value_matching(|value| value == expected_value, timeout)
+    }
+
+    /// Waits until the property exists and has one of the accepted values, and returns the value
+    /// that matched.
+    ///
+    /// The current value is checked before waiting for any changes. Returns
+    /// [`PropertyWatcherError::Timeout`] if the timeout elapses first. If `timeout` is `None`, the
+    /// default timeout set via [`PropertyWatcherBuilder`] is used, if any.
+    pub fn wait_for_any_value(
+        &mut self,
+        accepted: &[&str],
+        timeout: Option<Duration>,
+    ) -> Result<String> {
+        let mut matched = None;
+        self.wait_for_value_matching(
+            |value| {
+                matched = accepted.iter().find(|a| **a == value).copied();
+                matched.is_some()
+            },
+            timeout,
+        )?;
+        Ok(matched.unwrap_or_default().to_owned())
+    }
+
+    /// Waits until the property exists and `pred` returns true for its value.
+    ///
+    /// The current value is checked before waiting for any changes. Returns
+    /// [`PropertyWatcherError::Timeout`] if the timeout elapses first. If `timeout` is `None`, the
+    /// default timeout set via [`PropertyWatcherBuilder`] is used, if any.
+    pub fn wait_for_value_matching<F>(&mut self, pred: F, timeout: Option<Duration>) -> Result<()>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        self.wait_for_value_matching_counted(pred, timeout).map(|_| ())
+    }
+
+    /// Waits until the property exists and has the given value, like [`Self::wait_for_value`],
+    /// and returns the number of change notifications observed before the value appeared.
+    ///
+    /// The count is 0 if the property already had the value. Each notification after that may
+    /// cover several changes that happened in quick succession, so this is a measure of how often
+    /// the waiting thread was woken up for a value it wasn't interested in, rather than an exact
+    /// count of changes.
+    pub fn wait_for_value_counted(
+        &mut self,
+        expected_value: &str,
+        timeout: Option<Duration>,
+    ) -> Result<u32> {
+        self.wait_for_value_matching_counted(|value| value == expected_value, timeout)
+    }
+
+    // Implements wait_for_value_matching, returning the number of change notifications.
+    fn wait_for_value_matching_counted<F>(
+        &mut self,
+        mut pred: F,
+        timeout: Option<Duration>,
+    ) -> Result<u32>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        let until = timeout.or(self.default_timeout).and_then(deadline_after);
 
         self.wait_for_property_creation_until(until)?;
 
-        while self.read(|_, value| Ok(value != expected_value))? {
+        let mut count = 0u32;
+        while self.read(|_, value| Ok(!pred(value)))? {
             self.wait_for_property_change_until(until)?;
+            count = count.saturating_add(1);
         }
 
-        Ok(())
+        Ok(count)
+    }
+
+    /// Waits until the property exists and has the given value, re-reading the value at least
+    /// every `poll_interval` in addition to waiting for changes.
+    ///
+    /// This is a safety net for kernels where `__system_property_wait` has been observed to miss
+    /// wakeups. It returns as soon as either the wait or a periodic re-read observes the expected
+    /// value. Use [`Self::wait_for_value`] if lost wakeups aren't a concern.
+    ///
+    /// Returns [`PropertyWatcherError::Timeout`] if the timeout elapses first. If `timeout` is
+    /// `None`, the default timeout set via [`PropertyWatcherBuilder`] is used, if any.
+    pub fn wait_for_value_with_poll(
+        &mut self,
+        expected_value: &str,
+        timeout: Option<Duration>,
+        poll_interval: Duration,
+    ) -> Result<()> {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        let until = timeout.or(self.default_timeout).and_then(deadline_after);
+
+        loop {
+            if self.get_prop_info().is_some()
+                && self.read(|_, value| Ok(value == expected_value))?
+            {
+                return Ok(());
+            }
+
+            let slice_until = earliest(until, poll_interval);
+            let result = if self.prop_info.is_none() {
+                self.wait_for_property_creation_until(slice_until)
+            } else {
+                self.wait_for_property_change_until(slice_until)
+            };
+
+            match result {
+                Err(PropertyWatcherError::Timeout { .. })
+                    if until.is_none_or(|until| Instant::now() < until) => {}
+                r => r?,
+            }
+        }
+    }
+
+    /// Waits until the property is absent, or, if enabled via
+    /// [`PropertyWatcherBuilder::empty_is_absent`], until its value is empty.
+    ///
+    /// Returns immediately if the property doesn't exist. Note that Android doesn't remove
+    /// properties once they're created, so on a device a property that exists can only become
+    /// absent if the property area is recreated. Properties are conventionally "cleared" by
+    /// setting them to an empty value, so most callers will want to enable `empty_is_absent`.
+    ///
+    /// Returns [`PropertyWatcherError::Timeout`] if the timeout elapses first. If `timeout` is
+    /// `None`, the default timeout set via [`PropertyWatcherBuilder`] is used, if any.
+    pub fn wait_until_absent(&mut self, timeout: Option<Duration>) -> Result<()> {
+        #[cfg(feature = "observability")]
+        self.observe(|name| observer::Access::Wait { name });
+        let until = timeout.or(self.default_timeout).and_then(deadline_after);
+
+        loop {
+            if self.get_prop_info().is_none() {
+                return Ok(());
+            }
+            let empty_is_absent = self.empty_is_absent;
+            match self.read(|_, value| Ok(empty_is_absent && value.is_empty())) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                // The property vanished after it was looked up.
+                Err(PropertyWatcherError::ReadCallbackNotCalled) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            self.wait_for_property_change_until(until)?;
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+type ChangeFuture<'a> =
+    Pin<Box<dyn Future<Output = (&'a mut PropertyWatcher, Result<String>)> + Send + 'a>>;
+
+/// Stream returned by [`PropertyWatcher::changes`].
+#[cfg(feature = "tokio")]
+struct Changes<'a> {
+    next: ChangeFuture<'a>,
+}
+
+#[cfg(feature = "tokio")]
+impl<'a> Changes<'a> {
+    fn next(watcher: &'a mut PropertyWatcher) -> ChangeFuture<'a> {
+        Box::pin(async move {
+            let result = match watcher.wait_async(None).await {
+                Ok(()) => watcher.current(),
+                Err(e) => Err(e),
+            };
+            (watcher, result)
+        })
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl futures_core::Stream for Changes<'_> {
+    type Item = Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let (watcher, result) = std::task::ready!(self.next.as_mut().poll(cx));
+        self.next = Self::next(watcher);
+        Poll::Ready(Some(result))
+    }
+}
+
+/// Sets the wrapped flag when dropped, signalling a blocking task to stop.
+#[cfg(feature = "tokio")]
+#[derive(Default)]
+struct CancelOnDrop(Arc<AtomicBool>);
+
+#[cfg(feature = "tokio")]
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
     }
 }
 
 /// Reads a system property.
 ///
-/// Returns `Ok(None)` if the property doesn't exist.
+/// Returns `Ok(None)` if the property doesn't exist or if no value could be read from it, such
+/// as when `__system_property_read_callback` doesn't call the callback. Use [`try_read`] to
+/// distinguish these cases.
+///
+/// Each call looks up the property again. To read the same property repeatedly, create a
+/// [`PropertyWatcher`] once and use [`PropertyWatcher::current`] instead.
 pub fn read(name: &str) -> Result<Option<String>> {
+    match try_read(name) {
+        Err(PropertyWatcherError::ReadCallbackNotCalled) => Ok(None),
+        r => r,
+    }
+}
+
+/// Reads a system property, distinguishing an absent property from an empty one.
+///
+/// Returns `Ok(None)` only if the property doesn't exist. A property that exists with an empty
+/// value is returned as `Ok(Some(String::new()))`. Any other failure to read the value is
+/// returned as an error, including [`PropertyWatcherError::ReadCallbackNotCalled`].
+pub fn try_read(name: &str) -> Result<Option<String>> {
     match PropertyWatcher::new(name)?.read(|_name, value| Ok(value.to_owned())) {
         Ok(value) => Ok(Some(value)),
-        Err(PropertyWatcherError::SystemPropertyAbsent) => Ok(None),
+        Err(PropertyWatcherError::SystemPropertyAbsent { .. }) => Ok(None),
         Err(e) => Err(e),
     }
 }
 
-fn parse_bool(value: &str) -> Option<bool> {
-    match value {
-        "1" | "y" | "yes" | "on" | "true" => Some(true),
-        "0" | "n" | "no" | "off" | "false" => Some(false),
-        _ => None,
+/// Reads a system property like [`read`], but gives up after `timeout`.
+///
+/// `__system_property_read_callback` doesn't take a timeout, so the read is done on a new thread
+/// and [`PropertyWatcherError::Timeout`] is returned if it doesn't finish in time. In that case,
+/// the thread is left running and the FFI call may still complete later. Its result is discarded.
+/// Spawning a thread makes this much slower than [`read`] in the common case, so it should only
+/// be used where an unbounded read is unacceptable.
+pub fn read_timeout(name: &str, timeout: Duration) -> Result<Option<String>> {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+    let owned_name = name.to_owned();
+    std::thread::Builder::new()
+        .name("read_timeout".to_owned())
+        .spawn(move || {
+            // The receiver is gone if the read timed out.
+            let _ = sender.send(read(&owned_name));
+        })
+        .map_err(|_| PropertyWatcherError::WaitFailed { name: name.to_owned() })?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            Err(PropertyWatcherError::Timeout { name: name.to_owned() })
+        }
+        // The thread panicked.
+        Err(RecvTimeoutError::Disconnected) => {
+            Err(PropertyWatcherError::WaitFailed { name: name.to_owned() })
+        }
     }
 }
 
-/// Returns the duration remaining until the given instant.
-///
-/// Returns `None` if `None` is passed in, or `Some(0)` if `until` is in the past.
-fn remaining_time_until(until: Option<Instant>) -> Option<timespec> {
-    until.map(|until| {
-        duration_to_timespec(until.checked_duration_since(Instant::now()).unwrap_or_default())
-    })
+/// Reads a system property, or returns `default` if the property doesn't exist.
+pub fn read_or(name: &str, default: &str) -> Result<String> {
+    read_or_else(name, || default.to_owned())
 }
 
-/// Converts the given `Duration` to a C `timespec`.
-fn duration_to_timespec(duration: Duration) -> timespec {
-    timespec {
-        tv_sec: duration.as_secs().try_into().unwrap(),
-        tv_nsec: duration.subsec_nanos() as _,
-    }
+/// Reads a system property, or returns the result of `f` if the property doesn't exist.
+pub fn read_or_else<F: FnOnce() -> String>(name: &str, f: F) -> Result<String> {
+    Ok(read(name)?.unwrap_or_else(f))
 }
 
-/// Returns true if the system property `name` has the value "1", "y", "yes", "on", or "true",
-/// false for "0", "n", "no", "off", or "false", or `default_value` otherwise.
-pub fn read_bool(name: &str, default_value: bool) -> Result<bool> {
-    Ok(read(name)?.as_deref().and_then(parse_bool).unwrap_or(default_value))
+/// Returns whether a system property exists, without reading its value.
+pub fn exists(name: &str) -> Result<bool> {
+    Ok(PropertyWatcher::new(name)?.exists())
 }
 
-/// Writes a system property.
-pub fn write(name: &str, value: &str) -> Result<()> {
-    if
-    // SAFETY: Input and output are both const and valid strings.
-    unsafe {
-        // If successful, __system_property_set returns 0, otherwise, returns -1.
-        system_properties_bindgen::__system_property_set(
-            CString::new(name).context("Failed to construct CString from name.")?.as_ptr(),
-            CString::new(value).context("Failed to construct CString from value.")?.as_ptr(),
-        )
-    } == 0
-    {
-        Ok(())
-    } else {
-        Err(PropertyWatcherError::SetPropertyFailed)
+/// Reads a system property as raw bytes, without requiring the value to be valid UTF-8.
+///
+/// Returns `Ok(None)` if the property doesn't exist.
+pub fn read_bytes(name: &str) -> Result<Option<Vec<u8>>> {
+    match PropertyWatcher::new(name)?.read_raw_bytes(|_name, value| Ok(value.to_vec())) {
+        Ok(value) => Ok(Some(value)),
+        Err(PropertyWatcherError::SystemPropertyAbsent { .. }) => Ok(None),
+        Err(e) => Err(e),
     }
 }
 
-/// Iterates through the properties (that the current process is allowed to access).
-pub fn foreach<F>(mut f: F) -> Result<()>
-where
-    F: FnMut(&str, &str),
-{
-    extern "C" fn read_callback<F: FnMut(&str, &str)>(
+/// Reads a system property as an owned C string, for passing it on to C code.
+///
+/// Returns `Ok(None)` if the property doesn't exist. Like [`read_bytes`], the value isn't required
+/// to be valid UTF-8, and since it comes from bionic as a C string, it isn't checked for NUL
+/// characters again either.
+pub fn read_cstr(name: &str) -> Result<Option<CString>> {
+    match PropertyWatcher::new(name)?.read_cstr(|_name, value| Ok(value.to_owned())) {
+        Ok(value) => Ok(Some(value)),
+        Err(PropertyWatcherError::SystemPropertyAbsent { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Number of names above which [`read_many`] reads all properties in a single pass instead of
+/// looking up each one. This is a heuristic that hasn't been measured.
+const READ_MANY_FOREACH_THRESHOLD: usize = 32;
+
+/// Reads several system properties. The results are in the same order as `names`, with `None`
+/// for properties that don't exist.
+///
+/// Looking up a property is cheap, but not free, since the property area is a trie that has to
+/// be walked for each name, while enumerating all properties visits every one of them once. As a
+/// heuristic, when more than a few dozen names are requested, this reads every property in a
+/// single [`foreach`] pass and picks out the requested ones. Otherwise, each property is read
+/// individually with [`read`]. The results are the same either way.
+pub fn read_many(names: &[&str]) -> Result<Vec<Option<String>>> {
+    if names.len() <= READ_MANY_FOREACH_THRESHOLD {
+        return names.iter().map(|name| read(name)).collect();
+    }
+
+    // Fail the same way as read() for names that can't be converted to C strings.
+    for name in names {
+        CString::new(*name).map_err(bad_name_error)?;
+    }
+
+    let wanted = names.iter().copied().collect::<HashSet<_>>();
+    let mut found = HashMap::with_capacity(wanted.len());
+    let mut error = None;
+    foreach_raw(|name, value| {
+        let Some(name) = name.to_str().ok().filter(|name| wanted.contains(name)) else {
+            return;
+        };
+        match value.to_str() {
+            Ok(value) => {
+                found.insert(name.to_owned(), value.to_owned());
+            }
+            Err(e) => error = Some(e),
+        }
+    })?;
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+
+    Ok(names.iter().map(|name| found.get(*name).cloned()).collect())
+}
+
+/// Calls `f` with the value of a system property without allocating a copy of the value.
+///
+/// Returns `Ok(None)` if the property doesn't exist.
+pub fn with_value<T, F: FnOnce(&str) -> T>(name: &str, f: F) -> Result<Option<T>> {
+    let mut f = Some(f);
+    match PropertyWatcher::new(name)?.read(|_name, value| Ok(f.take().map(|f| f(value)))) {
+        Ok(result) => Ok(result),
+        Err(PropertyWatcherError::SystemPropertyAbsent { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the error for a value of the system property `name` that couldn't be parsed.
+fn parse_error(name: &str, error: String) -> SysPropError {
+    SysPropError::ParseError(parsers_formatters::with_property_name(name, error))
+}
+
+/// Reads a system property and parses it as a `T`.
+///
+/// Returns `Ok(None)` if the property doesn't exist.
+pub fn read_parsed<T: FromStr>(name: &str) -> std::result::Result<Option<T>, SysPropError> {
+    with_value(name, parsers_formatters::parse::<T>)
+        .map_err(SysPropError::FetchError)?
+        .transpose()
+        .map_err(|e| parse_error(name, e))
+}
+
+/// Reads a system property as a comma-separated list of `T`s.
+///
+/// Returns `Ok(None)` if the property doesn't exist. See [`parsers_formatters::parse_list`] for
+/// the format, which is the one written by [`write_list`].
+pub fn read_list<T: FromStr>(name: &str) -> std::result::Result<Option<Vec<T>>, SysPropError> {
+    with_value(name, parsers_formatters::parse_list::<T>)
+        .map_err(SysPropError::FetchError)?
+        .transpose()
+        .map_err(|e| parse_error(name, e))
+}
+
+/// Reads a system property as an integer.
+///
+/// Returns `Ok(None)` if the property doesn't exist. A value that can't be parsed is reported as
+/// [`SysPropError::ParseError`], whose message includes the name and value, rather than as `None`.
+pub fn read_integer<T: FromStr>(name: &str) -> std::result::Result<Option<T>, SysPropError> {
+    read_parsed(name)
+}
+
+/// Reads a system property as a `u32`. See [`read_integer`].
+pub fn read_u32(name: &str) -> std::result::Result<Option<u32>, SysPropError> {
+    read_integer(name)
+}
+
+/// Reads a system property as an `i64`. See [`read_integer`].
+pub fn read_i64(name: &str) -> std::result::Result<Option<i64>, SysPropError> {
+    read_integer(name)
+}
+
+/// Reads a system property as an `f64`.
+///
+/// Returns `Ok(None)` if the property doesn't exist. The value is parsed with [`f64::from_str`],
+/// so it must use `.` as the decimal separator regardless of locale and may use exponents, like
+/// `1.5e3`. `inf`, `infinity`, and `nan` (in any case, optionally signed) are accepted as well,
+/// since they're what formatting non-finite values produces, so callers that need a finite value
+/// should check [`f64::is_finite`]. Surrounding whitespace is not accepted.
+pub fn read_f64(name: &str) -> std::result::Result<Option<f64>, SysPropError> {
+    read_parsed(name)
+}
+
+/// Reads a system property as a dotted version number, like `ro.build.version.release`.
+///
+/// Returns `Ok(None)` if the property doesn't exist. See [`parsers_formatters::parse_version`] for
+/// the accepted format.
+pub fn read_version(
+    name: &str,
+) -> std::result::Result<Option<parsers_formatters::Version>, SysPropError> {
+    with_value(name, parsers_formatters::parse_version)
+        .map_err(SysPropError::FetchError)?
+        .transpose()
+        .map_err(|e| parse_error(name, e))
+}
+
+/// Reads a system property as a duration, like `5000` or `5s`.
+///
+/// Returns `Ok(None)` if the property doesn't exist. Values without a unit suffix are in
+/// `default_unit`. See [`parsers_formatters::parse_duration`] for the accepted format.
+pub fn read_duration(
+    name: &str,
+    default_unit: Duration,
+) -> std::result::Result<Option<Duration>, SysPropError> {
+    with_value(name, |value| parsers_formatters::parse_duration(value, default_unit))
+        .map_err(SysPropError::FetchError)?
+        .transpose()
+        .map_err(|e| parse_error(name, e))
+}
+
+/// Returns the serial number of the system property area.
+///
+/// The serial number changes whenever any system property is added or changed, so comparing it
+/// to an earlier snapshot is a cheap way to check whether anything changed without enumerating
+/// all properties. If the property area isn't initialized, bionic returns `u32::MAX`.
+pub fn area_serial() -> u32 {
+    // SAFETY: The function takes no arguments and only reads the property area's serial number.
+    unsafe { sys::__system_property_area_serial() }
+}
+
+/// Returns whether the system property area is initialized and usable by this process.
+///
+/// This is a cheap check that doesn't enumerate or look up any property. It only detects
+/// failures at runtime. The `__system_property_*` functions are provided by bionic, so this crate
+/// only links on Android targets, unless the `test-mock` feature is enabled, in which case the
+/// in-process store is used off-device and is always available. Code that runs on other
+/// platforms should only depend on this crate for `target_os = "android"`.
+pub fn is_available() -> bool {
+    area_serial() != u32::MAX
+}
+
+/// Returns the current global serial number of the system property area without blocking.
+///
+/// This is the same as [`area_serial`] and never fails.
+#[deprecated(note = "use `area_serial` instead")]
+pub fn global_serial() -> Result<u32> {
+    Ok(area_serial())
+}
+
+/// The values that [`read_bool`] accepts as true. They're compared ignoring ASCII case and
+/// surrounding whitespace.
+pub const BOOL_TRUE_TOKENS: &[&str] = &["1", "y", "yes", "on", "true"];
+
+/// The values that [`read_bool`] accepts as false. See [`BOOL_TRUE_TOKENS`].
+pub const BOOL_FALSE_TOKENS: &[&str] = &["0", "n", "no", "off", "false"];
+
+/// Parses a boolean, ignoring case and surrounding whitespace.
+fn parse_bool(value: &str) -> Option<bool> {
+    parsers_formatters::parse_bool_lenient(value).ok()
+}
+
+/// Returns the error for a failed `__system_property_wait` call.
+///
+/// The call is considered to have timed out if the deadline `until` has passed.
+fn wait_error(until: Option<Instant>, name: String) -> PropertyWatcherError {
+    if until.is_some_and(|until| Instant::now() >= until) {
+        PropertyWatcherError::Timeout { name }
+    } else {
+        PropertyWatcherError::WaitFailed { name }
+    }
+}
+
+/// Returns the deadline `timeout` from now, or `None` if it's too far in the future to be
+/// represented, in which case the timeout is effectively infinite.
+fn deadline_after(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+/// Returns the earlier of `until` and the deadline `timeout` from now.
+fn earliest(until: Option<Instant>, timeout: Duration) -> Option<Instant> {
+    match (until, deadline_after(timeout)) {
+        (Some(until), Some(slice_until)) => Some(until.min(slice_until)),
+        (until, slice_until) => until.or(slice_until),
+    }
+}
+
+/// Returns the time left until `until` as a relative timeout for `__system_property_wait`, or
+/// `None` for no deadline. A deadline that has already passed yields a zero timeout.
+fn remaining_time_until(until: Option<Instant>) -> Option<timespec> {
+    until.map(|until| {
+        duration_to_timespec(until.checked_duration_since(Instant::now()).unwrap_or_default())
+    })
+}
+
+/// Converts the given `Duration` to a C `timespec`.
+fn duration_to_timespec(duration: Duration) -> timespec {
+    timespec {
+        // Saturate so that absurdly long timeouts behave like very long ones instead of panicking.
+        tv_sec: duration.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+        tv_nsec: duration.subsec_nanos() as _,
+    }
+}
+
+/// Returns true if the system property `name` has one of the values in [`BOOL_TRUE_TOKENS`]
+/// ("1", "y", "yes", "on", or "true"), false for one in [`BOOL_FALSE_TOKENS`] ("0", "n", "no",
+/// "off", or "false"), or `default_value` otherwise. Case and surrounding whitespace are ignored.
+pub fn read_bool(name: &str, default_value: bool) -> Result<bool> {
+    Ok(read(name)?.as_deref().and_then(parse_bool).unwrap_or(default_value))
+}
+
+/// Returns whether `name` is a legal system property name.
+///
+/// This mirrors `IsLegalPropertyName` in system/core/init/util.cpp: the name must be non-empty,
+/// must not start or end with `.` or contain `..`, and may only contain ASCII alphanumerics and
+/// `.`, `_`, `-`, `@`, or `:`. Since API level 26, there is no limit on the length of the name.
+pub fn is_legal_property_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && name.bytes().all(|c| c.is_ascii_alphanumeric() || b"._-@:".contains(&c))
+}
+
+/// Returns the maximum length in bytes of a property name on this device, or `None` if there is no
+/// limit.
+///
+/// Devices older than API level 26 limit names to [`PROP_NAME_MAX`] - 1 bytes. The API level is
+/// read from `ro.build.version.sdk` the first time this is called. If it's absent or invalid, such
+/// as off-device, names are assumed to be unlimited.
+pub fn max_name_len() -> Option<usize> {
+    max_name_len_for_sdk(sdk_version())
+}
+
+/// Returns the API level from `ro.build.version.sdk`, or `None` if it's absent or invalid.
+///
+/// The property is read-only, so it's only read once. The mock store is the exception, since
+/// tests can change any property at any time, so it's read on every call there.
+fn sdk_version() -> Option<u32> {
+    fn read_sdk() -> Option<u32> {
+        read("ro.build.version.sdk").ok().flatten().and_then(|sdk| sdk.trim().parse().ok())
+    }
+
+    #[cfg(feature = "test-mock")]
+    if mock::is_enabled() {
+        return read_sdk();
+    }
+    static SDK: OnceLock<Option<u32>> = OnceLock::new();
+    *SDK.get_or_init(read_sdk)
+}
+
+fn max_name_len_for_sdk(sdk: Option<u32>) -> Option<usize> {
+    sdk.filter(|&sdk| sdk < UNLIMITED_NAME_SDK).map(|_| PROP_NAME_MAX - 1)
+}
+
+/// Returns whether the system property named `name` is read-only.
+///
+/// Read-only properties are the ones whose names start with `ro.`. They can only be set once,
+/// usually by init while loading the build properties, and never change afterwards. This is purely
+/// a naming convention enforced by init and bionic, so the property doesn't need to exist.
+pub fn is_read_only(name: &str) -> bool {
+    name.starts_with("ro.")
+}
+
+/// Writes a system property.
+///
+/// Names containing a NUL character are rejected with [`PropertyWatcherError::BadNameError`], and
+/// other names that aren't legal according to [`is_legal_property_name`] with
+/// [`PropertyWatcherError::IllegalName`]. Names longer than [`max_name_len`] are rejected with
+/// [`PropertyWatcherError::NameTooLong`]. Values of [`PROP_VALUE_MAX`] bytes or longer are
+/// rejected with [`PropertyWatcherError::ValueTooLong`] unless the property is an `ro.` property.
+pub fn write(name: &str, value: &str) -> Result<()> {
+    Writer::new().write(name, value)
+}
+
+/// Writes a system property whose value isn't necessarily valid UTF-8.
+///
+/// The value is checked like in [`write`], and must not contain NUL characters.
+pub fn write_bytes(name: &str, value: &[u8]) -> Result<()> {
+    Writer::new().write_bytes(name, value)
+}
+
+/// Writes several system properties in order, stopping at the first failure.
+///
+/// The same buffers are reused for every property. See [`Writer`].
+pub fn write_many(pairs: &[(&str, &str)]) -> Result<()> {
+    let mut writer = Writer::new();
+    pairs.iter().try_for_each(|(name, value)| writer.write(name, value))
+}
+
+/// Writer writes system properties, reusing its buffers for the NUL-terminated copies of the
+/// names and values that `__system_property_set` needs.
+///
+/// [`write`] allocates new buffers for every call. When writing many properties, creating one
+/// Writer and using it for all of them avoids that churn.
+#[derive(Debug, Default)]
+pub struct Writer {
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Writer {
+    /// Creates a Writer with empty buffers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a system property. See [`write`].
+    pub fn write(&mut self, name: &str, value: &str) -> Result<()> {
+        self.write_bytes(name, value.as_bytes())
+    }
+
+    /// Writes a system property whose value isn't necessarily valid UTF-8. See [`write_bytes`].
+    pub fn write_bytes(&mut self, name: &str, value: &[u8]) -> Result<()> {
+        self.write_bytes_with_max_name_len(name, value, max_name_len())
+    }
+
+    // Like write_bytes, but with the name length limit passed in, so that it can be tested without
+    // changing the API level of the whole process.
+    fn write_bytes_with_max_name_len(
+        &mut self,
+        name: &str,
+        value: &[u8],
+        max_name_len: Option<usize>,
+    ) -> Result<()> {
+        #[cfg(feature = "observability")]
+        observer::notify(observer::Access::Write { name });
+        // Check for NUL first, since is_legal_property_name also rejects it.
+        let c_name = to_cstr(&mut self.name, name.as_bytes()).map_err(|position| {
+            PropertyWatcherError::BadNameError { name: name.to_owned(), position }
+        })?;
+        if !is_legal_property_name(name) {
+            return Err(PropertyWatcherError::IllegalName(name.to_owned()));
+        }
+        if let Some(max) = max_name_len.filter(|&max| name.len() > max) {
+            return Err(PropertyWatcherError::NameTooLong { len: name.len(), max });
+        }
+        if value.len() >= PROP_VALUE_MAX && !is_read_only(name) {
+            return Err(PropertyWatcherError::ValueTooLong {
+                len: value.len(),
+                max: PROP_VALUE_MAX - 1,
+            });
+        }
+
+        let c_value = to_cstr(&mut self.value, value).map_err(|position| {
+            PropertyWatcherError::BadValueError { name: name.to_owned(), position }
+        })?;
+
+        // __system_property_set doesn't always set errno on failure, so clear it to avoid
+        // reporting a stale value.
+        clear_errno();
+        if
+        // SAFETY: Input and output are both const and valid strings.
+        unsafe {
+            // If successful, __system_property_set returns 0, otherwise, returns -1.
+            sys::__system_property_set(c_name.as_ptr(), c_value.as_ptr())
+        } == 0
+        {
+            Ok(())
+        } else {
+            let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+            Err(PropertyWatcherError::SetPropertyFailed { errno })
+        }
+    }
+}
+
+/// Copies `s` into `buf` with a NUL terminator and returns it as a C string. If `s` contains a
+/// NUL character, returns the position of the first one instead.
+fn to_cstr<'a>(buf: &'a mut Vec<u8>, s: &[u8]) -> std::result::Result<&'a CStr, usize> {
+    if let Some(position) = s.iter().position(|&b| b == 0) {
+        return Err(position);
+    }
+    buf.clear();
+    buf.extend_from_slice(s);
+    buf.push(0);
+    // SAFETY: The only NUL character is the terminator, since `s` was checked above.
+    Ok(unsafe { CStr::from_bytes_with_nul_unchecked(buf) })
+}
+
+/// Returns the error for a property name that couldn't be converted to a C string.
+fn bad_name_error(error: NulError) -> PropertyWatcherError {
+    let position = error.nul_position();
+    // The name was a &str, so this is never lossy.
+    let name = String::from_utf8_lossy(&error.into_vec()).into_owned();
+    PropertyWatcherError::BadNameError { name, position }
+}
+
+/// Writes a boolean system property as `true` or `false`.
+///
+/// Both forms are accepted by [`read_bool`], as well as by `android::base::GetBoolProperty` and
+/// Java's `SystemProperties.getBoolean`. However, consumers that compare the value as a string,
+/// such as init's `on property:` triggers, need the exact form they expect. Most platform
+/// properties, like `sys.boot_completed` or `ro.debuggable`, use `1` and `0`, for which
+/// [`write_bool_as_int`] should be used instead.
+pub fn write_bool(name: &str, value: bool) -> Result<()> {
+    write(name, &parsers_formatters::format_bool(&value))
+}
+
+/// Writes a boolean system property as `1` or `0`. See [`write_bool`].
+pub fn write_bool_as_int(name: &str, value: bool) -> Result<()> {
+    write(name, &parsers_formatters::format_bool_as_int(&value))
+}
+
+/// Writes a list of values to a system property, separated by commas.
+///
+/// Commas and backslashes in the values are escaped, so the list can be read back unchanged with
+/// [`read_list`]. The one exception is a list containing only an empty string, which is written
+/// the same way as an empty list.
+pub fn write_list<T: ToString>(name: &str, values: &[T]) -> Result<()> {
+    write(name, &parsers_formatters::format_list(values))
+}
+
+/// Writes a system property, retrying up to `attempts` times in total, with `backoff` between
+/// attempts, if the write fails.
+///
+/// This is useful during early boot, when the property service may not be ready yet. Only
+/// [`PropertyWatcherError::SetPropertyFailed`] is retried. Other errors, such as
+/// [`PropertyWatcherError::IllegalName`] or [`PropertyWatcherError::ValueTooLong`], are
+/// deterministic and returned immediately. If every attempt fails, the last error is returned.
+/// An `attempts` of 0 is treated as 1.
+pub fn write_with_retry(name: &str, value: &str, attempts: u32, backoff: Duration) -> Result<()> {
+    let mut remaining = attempts.max(1);
+    loop {
+        remaining -= 1;
+        match write(name, value) {
+            Err(PropertyWatcherError::SetPropertyFailed { .. }) if remaining > 0 => {
+                std::thread::sleep(backoff);
+            }
+            r => return r,
+        }
+    }
+}
+
+/// Writes a system property and returns its previous value, or `None` if it didn't exist.
+///
+/// The read and the write are not atomic. Another writer may change the property in between, in
+/// which case the returned value is not the one that was overwritten. Use [`write_if_equals`] if
+/// the write must only happen when the value is unchanged.
+pub fn swap(name: &str, value: &str) -> Result<Option<String>> {
+    let old_value = read(name)?;
+    write(name, value)?;
+    Ok(old_value)
+}
+
+/// Writes a system property and returns whether the write succeeded.
+///
+/// This is for best-effort writes where the reason for a failure doesn't matter. By design, it
+/// hides every failure, including ones that indicate a bug in the caller, like an illegal name or
+/// a value that's too long. Use [`write`] when the cause of a failure needs to be reported.
+pub fn try_write(name: &str, value: &str) -> bool {
+    write(name, value).is_ok()
+}
+
+/// Writes a system property, typically a `persist.` one, and waits until reading it back returns
+/// the written value.
+///
+/// `__system_property_set` can return before the property service has applied the write, and a
+/// write rejected by the property service (eg. due to SELinux) may still appear to succeed. This
+/// gives a best-effort confirmation that the write was accepted. It can't confirm that init has
+/// flushed the value to disk since that isn't observable through the property API. Returns
+/// [`PropertyWatcherError::Timeout`] if the value isn't observed before the timeout elapses.
+pub fn write_persistent(name: &str, value: &str, timeout: Option<Duration>) -> Result<()> {
+    set_and_confirm(name, value, timeout)
+}
+
+/// Writes a system property and waits until the new value is observable by readers.
+///
+/// Readers in the same or other processes may briefly still see the old value after
+/// `__system_property_set` returns, so code that reads a property right after writing it, such
+/// as an integration test, should use this instead of [`write`]. Returns
+/// [`PropertyWatcherError::Timeout`] if the value isn't observed before the timeout elapses. If
+/// `timeout` is `None`, this waits indefinitely. See [`write_persistent`] for `persist.`
+/// properties.
+pub fn set_and_confirm(name: &str, value: &str, timeout: Option<Duration>) -> Result<()> {
+    write(name, value)?;
+    PropertyWatcher::new(name)?.wait_for_value(value, timeout)
+}
+
+/// Writes a system property and verifies that reading it back returns the written value.
+///
+/// `__system_property_set` only reports whether the request was sent to the property service.
+/// The property service can still reject or alter the write, eg. due to a property-specific
+/// SELinux rule, in which case the value read back differs. This waits up to
+/// [`WRITE_VERIFY_TIMEOUT`] for the value to propagate and then returns
+/// [`PropertyWatcherError::WriteMismatch`] with the value that was read back. See
+/// [`write_verified_with_timeout`] to use a different timeout.
+pub fn write_verified(name: &str, value: &str) -> Result<()> {
+    write_verified_with_timeout(name, value, WRITE_VERIFY_TIMEOUT)
+}
+
+/// Like [`write_verified`], but waits up to `timeout` for the written value to become visible.
+pub fn write_verified_with_timeout(name: &str, value: &str, timeout: Duration) -> Result<()> {
+    match set_and_confirm(name, value, Some(timeout)) {
+        Err(PropertyWatcherError::Timeout { .. }) => {
+            let actual = read(name)?;
+            // The value may have shown up just after the timeout elapsed.
+            if actual.as_deref() == Some(value) {
+                return Ok(());
+            }
+            Err(PropertyWatcherError::WriteMismatch {
+                name: name.to_owned(),
+                expected: value.to_owned(),
+                actual,
+            })
+        }
+        r => r,
+    }
+}
+
+/// Sets the calling thread's errno to 0.
+fn clear_errno() {
+    // SAFETY: The errno location is always valid for the calling thread.
+    unsafe {
+        #[cfg(target_os = "android")]
+        {
+            *libc::__errno() = 0;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            *libc::__errno_location() = 0;
+        }
+        #[cfg(target_vendor = "apple")]
+        {
+            *libc::__error() = 0;
+        }
+    }
+}
+
+/// Maximum number of times [`write_if_equals`] re-reads the property when it observes a
+/// concurrent change.
+const WRITE_IF_EQUALS_ATTEMPTS: usize = 3;
+
+/// Writes `new_value` to a system property only if its current value is `expected`, where `None`
+/// means the property must be absent. Returns whether the write happened.
+///
+/// This is not atomic since the property API has no compare-and-set operation. If the global
+/// serial number changes while the current value is being checked, the check is retried a few
+/// times before giving up and returning [`PropertyWatcherError::Contended`], without writing. A
+/// concurrent writer may still modify the property in the short window between the final check
+/// and the write.
+pub fn write_if_equals(name: &str, expected: Option<&str>, new_value: &str) -> Result<bool> {
+    write_if_equals_with_serial(name, expected, new_value, area_serial)
+}
+
+// Like write_if_equals, but with the source of the global serial number passed in, so that
+// contention can be simulated in tests.
+fn write_if_equals_with_serial(
+    name: &str,
+    expected: Option<&str>,
+    new_value: &str,
+    mut area_serial: impl FnMut() -> u32,
+) -> Result<bool> {
+    for _ in 0..WRITE_IF_EQUALS_ATTEMPTS {
+        let serial = area_serial();
+        if read(name)?.as_deref() != expected {
+            return Ok(false);
+        }
+        if area_serial() == serial {
+            write(name, new_value)?;
+            return Ok(true);
+        }
+    }
+    Err(PropertyWatcherError::Contended { name: name.to_owned() })
+}
+
+/// Iterates through the properties (that the current process is allowed to access).
+///
+/// Names and values that aren't valid UTF-8 are converted lossily, replacing invalid sequences
+/// with U+FFFD. Use [`foreach_raw`] to get the original bytes.
+pub fn foreach<F>(mut f: F) -> Result<()>
+where
+    F: FnMut(&str, &str),
+{
+    foreach_raw(|name, value| f(&name.to_string_lossy(), &value.to_string_lossy()))
+}
+
+/// Iterates through the properties (that the current process is allowed to access), like
+/// [`foreach`], but without validating that the names and values are UTF-8.
+///
+/// This avoids the cost of UTF-8 validation when enumerating many properties. Prefer [`foreach`]
+/// unless profiling shows the validation matters.
+///
+/// # Safety
+///
+/// Every property name and value visible to the current process must be valid UTF-8. Bionic
+/// doesn't enforce this, and vendor properties aren't guaranteed to be, so this is only sound if
+/// the contents of the property area are known, such as when all values are ASCII.
+pub unsafe fn foreach_assume_utf8<F>(mut f: F) -> Result<()>
+where
+    F: FnMut(&str, &str),
+{
+    foreach_raw(|name, value| {
+        // SAFETY: The caller guarantees that all property names and values are valid UTF-8.
+        let (name, value) = unsafe {
+            (
+                std::str::from_utf8_unchecked(name.to_bytes()),
+                std::str::from_utf8_unchecked(value.to_bytes()),
+            )
+        };
+        f(name, value)
+    })
+}
+
+/// Iterates through the properties (that the current process is allowed to access), passing the
+/// names and values as C strings.
+///
+/// Unlike [`foreach`], this doesn't validate that the names and values are UTF-8 or allocate.
+pub fn foreach_raw<F>(mut f: F) -> Result<()>
+where
+    F: FnMut(&CStr, &CStr),
+{
+    extern "C" fn read_callback<F: FnMut(&CStr, &CStr)>(
         res_p: *mut c_void,
         name: *const c_char,
         value: *const c_char,
         _: c_uint,
     ) {
-        // SAFETY: system property names are null-terminated C strings in UTF-8. See
-        // IsLegalPropertyName in system/core/init/util.cpp.
-        let name = unsafe { CStr::from_ptr(name) }.to_str().unwrap();
-        // SAFETY: system property values are null-terminated C strings in UTF-8. See
+        // SAFETY: system property names are null-terminated C strings. See IsLegalPropertyName in
+        // system/core/init/util.cpp.
+        let name = unsafe { CStr::from_ptr(name) };
+        // SAFETY: system property values are null-terminated C strings. See
         // IsLegalPropertyValue in system/core/init/util.cpp.
-        let value = unsafe { CStr::from_ptr(value) }.to_str().unwrap();
+        let value = unsafe { CStr::from_ptr(value) };
 
         let ptr = res_p as *mut F;
         // SAFETY: ptr points to the API user's callback, which was cast to `*mut c_void` below.
@@ -296,63 +1691,1129 @@ where
         f(name, value);
     }
 
-    extern "C" fn foreach_callback<F: FnMut(&str, &str)>(
+    extern "C" fn foreach_callback<F: FnMut(&CStr, &CStr)>(
         prop_info: *const PropInfo,
         res_p: *mut c_void,
     ) {
         // SAFETY: FFI call with an internal callback function in Rust, with other parameters
         // passed through.
-        unsafe {
-            system_properties_bindgen::__system_property_read_callback(
-                prop_info,
-                Some(read_callback::<F>),
-                res_p,
-            )
-        }
+        unsafe { sys::__system_property_read_callback(prop_info, Some(read_callback::<F>), res_p) }
     }
 
+    clear_errno();
     // SAFETY: FFI call with an internal callback function in Rust, and another client's callback
     // that's cast only for our own use right above.
     let retval = unsafe {
-        system_properties_bindgen::__system_property_foreach(
-            Some(foreach_callback::<F>),
-            &mut f as *mut F as *mut c_void,
-        )
+        sys::__system_property_foreach(Some(foreach_callback::<F>), &mut f as *mut F as *mut c_void)
     };
     if retval < 0 {
-        Err(PropertyWatcherError::Uninitialized)
+        // Our callbacks can't request early termination, so bionic only fails here when the
+        // property area isn't initialized. It bails out before calling any callback, so errno
+        // reflects the failure rather than something done by `f`.
+        let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Err(PropertyWatcherError::Uninitialized { errno })
     } else {
         Ok(())
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+/// Iterates through the properties (that the current process is allowed to access) and returns
+/// how many were visited.
+///
+/// A count of 0 means the property area is accessible, but empty.
+pub fn foreach_count<F>(mut f: F) -> Result<usize>
+where
+    F: FnMut(&str, &str),
+{
+    let mut count = 0;
+    foreach(|name, value| {
+        count += 1;
+        f(name, value);
+    })?;
+    Ok(count)
+}
 
-    #[test]
-    fn parse_bool_test() {
-        for s in ["1", "y", "yes", "on", "true"] {
-            assert_eq!(parse_bool(s), Some(true), "testing with {}", s);
-        }
-        for s in ["0", "n", "no", "off", "false"] {
-            assert_eq!(parse_bool(s), Some(false), "testing with {}", s);
-        }
-        for s in ["random", "00", "of course", "no way", "YES", "Off"] {
-            assert_eq!(parse_bool(s), None, "testing with {}", s);
+/// Iterates through the properties (that the current process is allowed to access), passing them
+/// to `f` in batches of up to `batch_size` name and value pairs.
+///
+/// Every batch except the last one is full. A `batch_size` of 0 is treated as 1. The buffer is
+/// reused between batches, so at most `batch_size` properties are held in memory at a time.
+/// `__system_property_foreach` can't be paused, so `f` is still called while the property area
+/// is being walked. Batching only controls how often `f` is called, eg. to write each batch to a
+/// file as one chunk.
+pub fn foreach_buffered<F>(batch_size: usize, mut f: F) -> Result<()>
+where
+    F: FnMut(&[(String, String)]),
+{
+    let batch_size = batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    foreach(|name, value| {
+        batch.push((name.to_owned(), value.to_owned()));
+        if batch.len() == batch_size {
+            f(&batch);
+            batch.clear();
         }
+    })?;
+    if !batch.is_empty() {
+        f(&batch);
     }
+    Ok(())
+}
 
-    #[test]
-    fn read_absent_bool_test() {
-        let prop = "certainly.does.not.exist";
-        assert!(matches!(read(prop), Ok(None)));
-        assert!(read_bool(prop, true).unwrap_or(false));
-        assert!(!read_bool(prop, false).unwrap_or(true));
-    }
+/// Iterates through the properties (that the current process is allowed to access) whose names
+/// start with `prefix`.
+///
+/// The filtering happens in Rust, not in the C layer, so every property is still visited by
+/// `__system_property_foreach`. Only the matching ones are passed to `f`.
+pub fn foreach_prefix<F>(prefix: &str, mut f: F) -> Result<()>
+where
+    F: FnMut(&str, &str),
+{
+    foreach(|name, value| {
+        if name.starts_with(prefix) {
+            f(name, value);
+        }
+    })
+}
+
+/// Iterates through the properties (that the current process is allowed to access) that aren't
+/// read-only according to [`is_read_only`].
+///
+/// This is a classification by naming convention only. Whether a property can actually be
+/// written also depends on the SELinux policy, which isn't visible through the property API, so
+/// writes to some of the properties passed to `f` may still fail. Probing by writing back the
+/// current value isn't done because every write, even one that doesn't change the value, bumps
+/// the serial number and can fire init's `on property:` triggers.
+pub fn foreach_writable<F>(mut f: F) -> Result<()>
+where
+    F: FnMut(&str, &str),
+{
+    foreach(|name, value| {
+        if !is_read_only(name) {
+            f(name, value);
+        }
+    })
+}
+
+/// Returns the first property (that the current process is allowed to access) for which `pred`
+/// returns true.
+///
+/// `__system_property_foreach` can't be stopped early, so every property is still visited after
+/// a match is found. However, `pred` isn't called again and nothing is allocated for the
+/// remaining properties, so the cost after the match is only the walk through the property area.
+pub fn find<F>(mut pred: F) -> Result<Option<(String, String)>>
+where
+    F: FnMut(&str, &str) -> bool,
+{
+    let mut found = None;
+    foreach(|name, value| {
+        if found.is_none() && pred(name, value) {
+            found = Some((name.to_owned(), value.to_owned()));
+        }
+    })?;
+    Ok(found)
+}
+
+/// Returns all properties (that the current process is allowed to access) as a map from name to
+/// value.
+pub fn snapshot() -> Result<HashMap<String, String>> {
+    // Devices typically have on the order of a thousand properties.
+    let mut properties = HashMap::with_capacity(1024);
+    foreach(|name, value| {
+        properties.insert(name.to_owned(), value.to_owned());
+    })?;
+    Ok(properties)
+}
+
+/// Returns the properties (that the current process is allowed to access) whose names start with
+/// `prefix` as a map from name to value.
+///
+/// The map is sorted by name, so the output is deterministic when it's logged or compared
+/// against golden files. Like [`foreach_prefix`], `prefix` is matched as a plain string, so pass
+/// `"keystore."` rather than `"keystore"` to exclude properties like `keystore_foo`.
+pub fn group_snapshot(prefix: &str) -> Result<BTreeMap<String, String>> {
+    let mut properties = BTreeMap::new();
+    foreach_prefix(prefix, |name, value| {
+        properties.insert(name.to_owned(), value.to_owned());
+    })?;
+    Ok(properties)
+}
+
+/// Returns an iterator over all properties (that the current process is allowed to access) as
+/// `(name, value)` pairs.
+///
+/// The properties are collected up front, so the iterator is a snapshot. It does not reflect
+/// changes made after `iter` returns.
+pub fn iter() -> Result<PropertyIter> {
+    let mut properties = Vec::new();
+    foreach(|name, value| properties.push((name.to_owned(), value.to_owned())))?;
+    Ok(PropertyIter(properties.into_iter()))
+}
+
+/// Iterator over a snapshot of the system properties. See [`iter`].
+#[derive(Debug)]
+pub struct PropertyIter(std::vec::IntoIter<(String, String)>);
+
+impl Iterator for PropertyIter {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for PropertyIter {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn send_sync_test() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PropertyWatcher>();
+        assert_send_sync::<PropertyWatcherBuilder>();
+        assert_send_sync::<MultiWatcher>();
+    }
+
+    #[test]
+    fn parse_bool_test() {
+        for s in ["1", "y", "yes", "on", "true"] {
+            assert_eq!(parse_bool(s), Some(true), "testing with {}", s);
+        }
+        for s in ["0", "n", "no", "off", "false"] {
+            assert_eq!(parse_bool(s), Some(false), "testing with {}", s);
+        }
+        for s in ["Yes", "TRUE", " on", "Y\n"] {
+            assert_eq!(parse_bool(s), Some(true), "testing with {:?}", s);
+        }
+        for s in [" off ", "FALSE", "No", "\t0"] {
+            assert_eq!(parse_bool(s), Some(false), "testing with {:?}", s);
+        }
+        for s in ["random", "00", "of course", "no way", "", " "] {
+            assert_eq!(parse_bool(s), None, "testing with {}", s);
+        }
+        for s in BOOL_TRUE_TOKENS {
+            assert_eq!(parse_bool(s), Some(true), "testing with {}", s);
+        }
+        for s in BOOL_FALSE_TOKENS {
+            assert_eq!(parse_bool(s), Some(false), "testing with {}", s);
+        }
+    }
+
+    #[test]
+    fn set_property_failed_display_test() {
+        let message = PropertyWatcherError::SetPropertyFailed { errno: libc::EACCES }.to_string();
+        let expected = std::io::Error::from_raw_os_error(libc::EACCES).to_string();
+        assert!(message.contains(&expected), "{}", message);
+        let message = PropertyWatcherError::SetPropertyFailed { errno: 0 }.to_string();
+        assert_eq!(message, "__system_property_set failed.");
+    }
+
+    #[test]
+    fn uninitialized_display_test() {
+        let error = PropertyWatcherError::Uninitialized { errno: 0 };
+        assert_eq!(error.to_string(), "System properties are not initialized.");
+        let error = PropertyWatcherError::Uninitialized { errno: libc::EACCES };
+        assert!(error.to_string().starts_with("System properties are not initialized: "));
+    }
+
+    #[test]
+    fn callback_error_display_test() {
+        let error = anyhow::anyhow!("inner").context("outer");
+        let error = PropertyWatcherError::CallbackError(error);
+        assert_eq!(error.to_string(), "Callback failed: outer: inner");
+        assert!(std::error::Error::source(&error).is_none());
+    }
+
+    #[test]
+    fn contended_display_test() {
+        let error = PropertyWatcherError::Contended { name: "a.b".to_owned() };
+        assert_eq!(
+            error.to_string(),
+            r#"System property "a.b" couldn't be compared because properties kept changing"#
+        );
+    }
+
+    #[test]
+    fn write_mismatch_display_test() {
+        let error = PropertyWatcherError::WriteMismatch {
+            name: "a.b".to_owned(),
+            expected: "1".to_owned(),
+            actual: None,
+        };
+        assert_eq!(
+            error.to_string(),
+            r#"System property "a.b" was set to "1", but reads back as None"#
+        );
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_cstr_test() {
+        assert_eq!(read_cstr("test.read_cstr").unwrap(), None);
+        mock::set_bytes("test.read_cstr", b"foo\xffbar");
+        assert_eq!(read_cstr("test.read_cstr").unwrap().unwrap().as_bytes(), b"foo\xffbar");
+        mock::set("test.read_cstr", "");
+        assert_eq!(read_cstr("test.read_cstr").unwrap().unwrap().as_bytes(), b"");
+    }
+
+    #[test]
+    fn max_name_len_test() {
+        assert_eq!(max_name_len_for_sdk(Some(25)), Some(PROP_NAME_MAX - 1));
+        assert_eq!(max_name_len_for_sdk(Some(26)), None);
+        assert_eq!(max_name_len_for_sdk(Some(34)), None);
+        assert_eq!(max_name_len_for_sdk(None), None);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn long_name_test() {
+        let name = format!("test.long_name.{}", "a".repeat(PROP_NAME_MAX));
+        let max = max_name_len_for_sdk(Some(25));
+        let mut writer = Writer::new();
+        assert!(matches!(
+            writer.write_bytes_with_max_name_len(&name, b"1", max),
+            Err(PropertyWatcherError::NameTooLong { len, max })
+                if len == name.len() && max == PROP_NAME_MAX - 1
+        ));
+        writer.write_bytes_with_max_name_len(&name[..PROP_NAME_MAX - 1], b"1", max).unwrap();
+
+        // The mock has no ro.build.version.sdk, so names are unlimited, like off-device.
+        assert_eq!(max_name_len(), None);
+        write(&name, "1").unwrap();
+        assert_eq!(read(&name).unwrap().as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn bad_name_test() {
+        let error = PropertyWatcher::new("a.b\0c").unwrap_err();
+        assert!(
+            matches!(&error, PropertyWatcherError::BadNameError { name, position: 3 } if name == "a.b\0c"),
+            "testing with {:?}",
+            error
+        );
+        assert_eq!(
+            error.to_string(),
+            r#"System property name "a.b\0c" contains a NUL character at byte 3"#
+        );
+
+        // The NUL is reported before the name is rejected as illegal.
+        assert!(matches!(
+            write("a.b\0c", "1"),
+            Err(PropertyWatcherError::BadNameError { position: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn name_test() {
+        let watcher = PropertyWatcher::new("certainly.does.not.exist").unwrap();
+        assert_eq!(watcher.name(), "certainly.does.not.exist");
+    }
+
+    #[test]
+    fn absent_error_name_test() {
+        let mut watcher = PropertyWatcher::new("certainly.does.not.exist").unwrap();
+        let error = watcher.current().unwrap_err();
+        assert!(error.to_string().contains("certainly.does.not.exist"), "{}", error);
+    }
+
+    #[test]
+    fn read_or_absent_test() {
+        let prop = "certainly.does.not.exist";
+        assert_eq!(read_or(prop, "default").unwrap(), "default");
+        assert_eq!(read_or_else(prop, || "default".to_owned()).unwrap(), "default");
+    }
+
+    #[test]
+    fn is_legal_property_name_test() {
+        for s in ["a", "ro.build.id", "persist.sys.foo_bar-baz", "vendor.x@1.0:y", "A1"] {
+            assert!(is_legal_property_name(s), "testing with {}", s);
+        }
+        for s in ["", ".", ".a", "a.", "a..b", "a b", "a/b", "a=b", "é"] {
+            assert!(!is_legal_property_name(s), "testing with {}", s);
+        }
+    }
+
+    #[test]
+    fn is_read_only_test() {
+        assert!(is_read_only("ro.build.id"));
+        assert!(!is_read_only("persist.sys.ro.foo"));
+        assert!(!is_read_only("ro"));
+        assert!(PropertyWatcher::new("ro.build.id").unwrap().is_read_only());
+        assert!(!PropertyWatcher::new("sys.boot_completed").unwrap().is_read_only());
+    }
+
+    #[test]
+    fn duration_to_timespec_test() {
+        let timespec = duration_to_timespec(Duration::new(1, 2));
+        assert_eq!((timespec.tv_sec, timespec.tv_nsec), (1, 2));
+        let timespec = duration_to_timespec(Duration::from_secs(u64::MAX));
+        assert_eq!(timespec.tv_sec, libc::time_t::MAX);
+        assert!(deadline_after(Duration::MAX).is_none());
+    }
+
+    #[test]
+    fn write_illegal_name_test() {
+        assert!(matches!(write("bad name", "1"), Err(PropertyWatcherError::IllegalName(_))));
+    }
+
+    #[test]
+    fn read_absent_bool_test() {
+        let prop = "certainly.does.not.exist";
+        assert!(matches!(read(prop), Ok(None)));
+        assert!(read_bool(prop, true).unwrap_or(false));
+        assert!(!read_bool(prop, false).unwrap_or(true));
+    }
+
+    #[test]
+    fn wait_absent_timeout_test() {
+        let mut watcher = PropertyWatcher::new("certainly.does.not.exist").unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            watcher.wait(Some(Duration::from_millis(50))),
+            Err(PropertyWatcherError::Timeout { name }) if name == "certainly.does.not.exist"
+        ));
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn try_read_empty_test() {
+        let prop = "test.try_read_empty";
+        assert!(matches!(try_read(prop), Ok(None)));
+        mock::set(prop, "");
+        assert_eq!(try_read(prop).unwrap().as_deref(), Some(""));
+        assert_eq!(read(prop).unwrap().as_deref(), Some(""));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn reset_test() {
+        let prop = "test.reset";
+        mock::set(prop, "a");
+        let mut watcher = PropertyWatcher::new(prop).unwrap();
+        assert_eq!(watcher.current().unwrap(), "a");
+        watcher.wait(None).unwrap();
+        assert_ne!(watcher.serial(), 0);
+        watcher.reset();
+        assert_eq!(watcher.serial(), 0);
+        assert_eq!(watcher.current().unwrap(), "a");
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_integer_test() {
+        let prop = "test.read_integer";
+        assert!(matches!(read_u32(prop), Ok(None)));
+        mock::set(prop, "42");
+        assert_eq!(read_u32(prop).unwrap(), Some(42));
+        assert_eq!(read_i64(prop).unwrap(), Some(42));
+        mock::set(prop, "-1");
+        assert_eq!(read_i64(prop).unwrap(), Some(-1));
+        assert!(matches!(read_u32(prop), Err(SysPropError::ParseError(e)) if e.contains("-1")));
+        assert!(matches!(read_u32(prop), Err(SysPropError::ParseError(e)) if e.contains(prop)));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn writer_test() {
+        let mut writer = Writer::new();
+        writer.write("test.writer.long_name", "long value").unwrap();
+        writer.write("test.writer", "1").unwrap();
+        assert_eq!(read("test.writer.long_name").unwrap().as_deref(), Some("long value"));
+        assert_eq!(read("test.writer").unwrap().as_deref(), Some("1"));
+        assert!(matches!(
+            writer.write("test.writer", "a\0b"),
+            Err(PropertyWatcherError::BadValueError { name, position: 1 }) if name == "test.writer"
+        ));
+        assert!(matches!(writer.write("bad name", "1"), Err(PropertyWatcherError::IllegalName(_))));
+        writer.write("test.writer", "2").unwrap();
+        assert_eq!(read("test.writer").unwrap().as_deref(), Some("2"));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_bytes_test() {
+        write_bytes("test.write_bytes", b"foo\xffbar").unwrap();
+        assert_eq!(read_bytes("test.write_bytes").unwrap().as_deref(), Some(&b"foo\xffbar"[..]));
+        assert!(matches!(
+            write_bytes("test.write_bytes", b"a\0b"),
+            Err(PropertyWatcherError::BadValueError { position: 1, .. })
+        ));
+        assert!(matches!(
+            write_bytes("test.write_bytes", &[b'a'; PROP_VALUE_MAX]),
+            Err(PropertyWatcherError::ValueTooLong { .. })
+        ));
+        assert!(matches!(write_bytes("bad name", b"1"), Err(PropertyWatcherError::IllegalName(_))));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_many_test() {
+        write_many(&[("test.write_many.a", "1"), ("test.write_many.b", "2")]).unwrap();
+        assert_eq!(read("test.write_many.a").unwrap().as_deref(), Some("1"));
+        assert_eq!(read("test.write_many.b").unwrap().as_deref(), Some("2"));
+        assert!(write_many(&[
+            ("test.write_many.c", "3"),
+            ("bad name", "4"),
+            ("test.write_many.d", "5")
+        ])
+        .is_err());
+        assert_eq!(read("test.write_many.c").unwrap().as_deref(), Some("3"));
+        assert_eq!(read("test.write_many.d").unwrap(), None);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn foreach_buffered_test() {
+        mock::set("test.foreach_buffered", "1");
+        let mut sizes = Vec::new();
+        let mut found = false;
+        foreach_buffered(3, |batch| {
+            sizes.push(batch.len());
+            found |=
+                batch.iter().any(|(name, value)| name == "test.foreach_buffered" && value == "1");
+        })
+        .unwrap();
+        assert!(found);
+        let (last, full) = sizes.split_last().unwrap();
+        assert!(full.iter().all(|&size| size == 3), "testing with {:?}", sizes);
+        assert!((1..=3).contains(last), "testing with {:?}", sizes);
+
+        let mut count = 0;
+        foreach_buffered(0, |batch| {
+            assert_eq!(batch.len(), 1);
+            count += 1;
+        })
+        .unwrap();
+        assert!(count > 0);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn foreach_writable_test() {
+        mock::set("test.foreach_writable", "1");
+        mock::set("ro.test.foreach_writable", "1");
+        let mut names = Vec::new();
+        foreach_writable(|name, _| {
+            if name.contains("test.foreach_writable") {
+                names.push(name.to_owned());
+            }
+        })
+        .unwrap();
+        assert_eq!(names, ["test.foreach_writable"]);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn find_test() {
+        mock::set("test.find", "needle");
+        assert_eq!(
+            find(|_, value| value == "needle").unwrap(),
+            Some(("test.find".to_owned(), "needle".to_owned()))
+        );
+        assert_eq!(find(|name, _| name == "certainly.does.not.exist").unwrap(), None);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_persistent_test() {
+        let prop = "persist.test.write_persistent";
+        write_persistent(prop, "1", Some(Duration::from_secs(10))).unwrap();
+        assert_eq!(read(prop).unwrap().as_deref(), Some("1"));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_until_absent_test() {
+        let mut watcher = PropertyWatcher::new("test.wait_until_absent.missing").unwrap();
+        watcher.wait_until_absent(Some(Duration::ZERO)).unwrap();
+
+        mock::set("test.wait_until_absent", "1");
+        let mut watcher = PropertyWatcher::new("test.wait_until_absent").unwrap();
+        assert!(matches!(
+            watcher.wait_until_absent(Some(Duration::from_millis(10))),
+            Err(PropertyWatcherError::Timeout { .. })
+        ));
+
+        let mut watcher = PropertyWatcherBuilder::new()
+            .name("test.wait_until_absent")
+            .empty_is_absent(true)
+            .build()
+            .unwrap();
+        let handle = std::thread::spawn(|| {
+            assert!(mock::wait_for_waiter(Some("test.wait_until_absent"), Duration::from_secs(10)));
+            mock::set("test.wait_until_absent", "");
+        });
+        watcher.wait_until_absent(Some(Duration::from_secs(10))).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[cfg(all(feature = "test-mock", feature = "tokio"))]
+    #[test]
+    fn changes_test() {
+        use futures_core::Stream;
+
+        mock::set("test.changes", "1");
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let mut watcher = PropertyWatcher::new("test.changes").unwrap();
+            let mut changes = std::pin::pin!(watcher.changes());
+            let next = std::future::poll_fn(|cx| changes.as_mut().poll_next(cx));
+            assert_eq!(next.await.unwrap().unwrap(), "1");
+
+            mock::set("test.changes", "2");
+            let next = std::future::poll_fn(|cx| changes.as_mut().poll_next(cx));
+            assert_eq!(next.await.unwrap().unwrap(), "2");
+        });
+    }
+
+    #[cfg(all(feature = "test-mock", feature = "tokio"))]
+    #[test]
+    fn wait_async_test() {
+        mock::set("test.wait_async", "1");
+        // With a single blocking thread, a later wait can only run once the task of an earlier,
+        // dropped wait has stopped.
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .max_blocking_threads(1)
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut absent = PropertyWatcher::new("test.wait_async.absent").unwrap();
+            let pending = tokio::time::timeout(Duration::from_millis(10), absent.wait_async(None));
+            assert!(pending.await.is_err());
+
+            let mut watcher = PropertyWatcher::new("test.wait_async").unwrap();
+            watcher.wait(None).unwrap();
+            // The change is seen whether it happens before or during the wait, since the serial
+            // number was recorded above.
+            let setter = tokio::spawn(async { mock::set("test.wait_async", "2") });
+            watcher.wait_async(Some(Duration::from_secs(10))).await.unwrap();
+            setter.await.unwrap();
+            assert_eq!(watcher.current().unwrap(), "2");
+        });
+    }
+
+    #[cfg(all(feature = "test-mock", feature = "tokio"))]
+    #[test]
+    fn wait_async_unbounded_creation_test() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let mut watcher = PropertyWatcherBuilder::new()
+                .name("test.wait_async_unbounded_creation")
+                .bound_creation_wait(false)
+                .build()
+                .unwrap();
+            let handle = std::thread::spawn(|| {
+                // The wait must not time out no matter how long creating the property takes.
+                std::thread::sleep(ASYNC_WAIT_SLICE * 2);
+                mock::set("test.wait_async_unbounded_creation", "1");
+            });
+            watcher.wait_async(Some(Duration::from_millis(10))).await.unwrap();
+            handle.join().unwrap();
+            assert!(watcher.exists());
+        });
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_many_test() {
+        mock::set("test.read_many.a", "a");
+        mock::set("test.read_many.b", "");
+        let names = ["test.read_many.b", "test.read_many.missing", "test.read_many.a"];
+        let expected = [Some(String::new()), None, Some("a".to_owned())];
+        assert_eq!(read_many(&names).unwrap(), expected);
+
+        // Exceed the threshold to exercise the single-pass implementation.
+        let names = names.repeat(READ_MANY_FOREACH_THRESHOLD);
+        let expected = expected.iter().cloned().cycle().take(names.len()).collect::<Vec<_>>();
+        assert_eq!(read_many(&names).unwrap(), expected);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_for_value_with_poll_test() {
+        let mut watcher = PropertyWatcher::new("test.wait_for_value_with_poll").unwrap();
+        assert!(matches!(
+            watcher.wait_for_value_with_poll(
+                "1",
+                Some(Duration::from_millis(50)),
+                Duration::from_millis(10)
+            ),
+            Err(PropertyWatcherError::Timeout { .. })
+        ));
+
+        let handle = std::thread::spawn(|| {
+            // The property doesn't exist yet, so the watcher waits for any property to change.
+            assert!(mock::wait_for_waiter(None, Duration::from_secs(10)));
+            mock::set("test.wait_for_value_with_poll", "0");
+            mock::set("test.wait_for_value_with_poll", "1");
+        });
+        watcher
+            .wait_for_value_with_poll("1", Some(Duration::from_secs(10)), Duration::from_millis(10))
+            .unwrap();
+        handle.join().unwrap();
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_with_retry_test() {
+        write_with_retry("test.write_with_retry", "1", 3, Duration::ZERO).unwrap();
+        assert_eq!(read("test.write_with_retry").unwrap().as_deref(), Some("1"));
+
+        // The mock refuses to change read-only properties, so every attempt fails.
+        mock::set("ro.test.write_with_retry", "1");
+        let start = Instant::now();
+        assert!(matches!(
+            write_with_retry("ro.test.write_with_retry", "2", 3, Duration::from_millis(20)),
+            Err(PropertyWatcherError::SetPropertyFailed { .. })
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        assert!(matches!(
+            write_with_retry("bad name", "1", 3, Duration::from_secs(10)),
+            Err(PropertyWatcherError::IllegalName(_))
+        ));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn list_test() {
+        assert!(read_list::<String>("test.list").unwrap().is_none());
+
+        let values = ["a,b", "\\", "", "c"];
+        write_list("test.list", &values).unwrap();
+        assert_eq!(read_list::<String>("test.list").unwrap().unwrap(), values);
+
+        write_list("test.list", &[1, 2, 3]).unwrap();
+        assert_eq!(read_list::<u32>("test.list").unwrap().unwrap(), [1, 2, 3]);
+        assert!(matches!(read_list::<bool>("test.list"), Err(SysPropError::ParseError(_))));
+
+        write_list::<u32>("test.list", &[]).unwrap();
+        assert_eq!(read_list::<u32>("test.list").unwrap().unwrap(), Vec::<u32>::new());
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_map_test() {
+        let mut watcher = PropertyWatcher::new("test.read_map").unwrap();
+        assert!(matches!(
+            watcher.read_map(str::len),
+            Err(PropertyWatcherError::SystemPropertyAbsent { .. })
+        ));
+        mock::set("test.read_map", "foo");
+        assert_eq!(watcher.read_map(str::len).unwrap(), 3);
+        assert!(watcher.read_map(|value| value == "foo").unwrap());
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_ok_test() {
+        let mut watcher = PropertyWatcher::new("test.read_ok").unwrap();
+        assert!(matches!(
+            watcher.read_ok(|_, value| value.len()),
+            Err(PropertyWatcherError::SystemPropertyAbsent { .. })
+        ));
+        mock::set("test.read_ok", "foo");
+        assert_eq!(
+            watcher.read_ok(|name, value| format!("{name}={value}")).unwrap(),
+            "test.read_ok=foo"
+        );
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn group_snapshot_test() {
+        mock::set("test.group_snapshot.b", "2");
+        mock::set("test.group_snapshot.a", "1");
+        mock::set("test.group_snapshot_other", "3");
+        let group = group_snapshot("test.group_snapshot.").unwrap();
+        assert_eq!(
+            group.into_iter().collect::<Vec<_>>(),
+            [
+                ("test.group_snapshot.a".to_owned(), "1".to_owned()),
+                ("test.group_snapshot.b".to_owned(), "2".to_owned()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_cancellable_test() {
+        mock::set("test.wait_cancellable", "1");
+        let mut watcher = PropertyWatcher::new("test.wait_cancellable").unwrap();
+        let cancel = AtomicBool::new(false);
+        // Resolve the property so that the first wait doesn't just wait for it to be created.
+        watcher.current().unwrap();
+        // The first wait returns immediately since the watcher hasn't seen any serial yet.
+        watcher.wait_cancellable(None, &cancel).unwrap();
+
+        assert!(matches!(
+            watcher.wait_cancellable(Some(Duration::from_millis(10)), &cancel),
+            Err(PropertyWatcherError::Timeout { .. })
+        ));
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(mock::wait_for_waiter(
+                    Some("test.wait_cancellable"),
+                    Duration::from_secs(10)
+                ));
+                cancel.store(true, Ordering::Relaxed);
+            });
+            assert!(matches!(
+                watcher.wait_cancellable(None, &cancel),
+                Err(PropertyWatcherError::Cancelled { name }) if name == "test.wait_cancellable"
+            ));
+        });
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_cancellable_unbounded_creation_test() {
+        let mut watcher = PropertyWatcherBuilder::new()
+            .name("test.wait_cancellable_unbounded_creation")
+            .bound_creation_wait(false)
+            .build()
+            .unwrap();
+        let cancel = AtomicBool::new(false);
+        let handle = std::thread::spawn(|| {
+            // Create the property well after both the timeout and the first cancellation check
+            // have passed. The wait must not time out no matter how long this takes.
+            std::thread::sleep(CANCEL_CHECK_INTERVAL * 2);
+            mock::set("test.wait_cancellable_unbounded_creation", "1");
+        });
+        watcher.wait_cancellable(Some(Duration::from_millis(10)), &cancel).unwrap();
+        handle.join().unwrap();
+        assert!(watcher.exists());
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_bool_test() {
+        write_bool("test.write_bool", true).unwrap();
+        assert_eq!(read("test.write_bool").unwrap().as_deref(), Some("true"));
+        assert!(read_bool("test.write_bool", false).unwrap());
+        write_bool_as_int("test.write_bool", false).unwrap();
+        assert_eq!(read("test.write_bool").unwrap().as_deref(), Some("0"));
+        assert!(!read_bool("test.write_bool", true).unwrap());
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_until_test() {
+        mock::set("test.wait_until", "1");
+        let mut watcher = PropertyWatcher::new("test.wait_until").unwrap();
+        watcher.current().unwrap();
+        assert_eq!(watcher.wait_until(Instant::now()).unwrap(), Duration::ZERO);
+
+        let timeout = Duration::from_millis(100);
+        for _ in 0..2 {
+            let start = Instant::now();
+            assert!(matches!(
+                watcher.wait_until(start + timeout),
+                Err(PropertyWatcherError::Timeout { .. })
+            ));
+            let elapsed = start.elapsed();
+            assert!(elapsed >= timeout, "testing with {:?}", elapsed);
+            assert!(elapsed < timeout * 5, "testing with {:?}", elapsed);
+        }
+
+        let start = Instant::now();
+        assert!(matches!(watcher.wait(Some(timeout)), Err(PropertyWatcherError::Timeout { .. })));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= timeout && elapsed < timeout * 5, "testing with {:?}", elapsed);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let handle = std::thread::spawn(|| {
+            assert!(mock::wait_for_waiter(Some("test.wait_until"), Duration::from_secs(10)));
+            mock::set("test.wait_until", "2");
+        });
+        let remaining = watcher.wait_until(deadline).unwrap();
+        handle.join().unwrap();
+        assert!(remaining > Duration::ZERO, "testing with {:?}", remaining);
+        assert!(remaining < Duration::from_secs(10), "testing with {:?}", remaining);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_for_value_counted_test() {
+        mock::set("test.wait_for_value_counted", "0");
+        let mut watcher = PropertyWatcher::new("test.wait_for_value_counted").unwrap();
+        let timeout = Some(Duration::from_secs(10));
+        assert_eq!(watcher.wait_for_value_counted("0", timeout).unwrap(), 0);
+
+        let handle = std::thread::spawn(|| {
+            for value in ["1", "2", "3"] {
+                assert!(mock::wait_for_waiter(
+                    Some("test.wait_for_value_counted"),
+                    Duration::from_secs(10)
+                ));
+                mock::set("test.wait_for_value_counted", value);
+            }
+        });
+        let count = watcher.wait_for_value_counted("3", timeout).unwrap();
+        handle.join().unwrap();
+        // The watcher hasn't recorded a serial number yet, so the first wait may return
+        // immediately, and the changes may be coalesced.
+        assert!((1..=4).contains(&count), "testing with {}", count);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_for_any_value_test() {
+        mock::set("test.wait_for_any_value", "failed");
+        let mut watcher = PropertyWatcher::new("test.wait_for_any_value").unwrap();
+        let accepted = ["done", "failed"];
+        assert_eq!(watcher.wait_for_any_value(&accepted, Some(Duration::ZERO)).unwrap(), "failed");
+
+        mock::set("test.wait_for_any_value", "running");
+        let handle = std::thread::spawn(|| {
+            assert!(mock::wait_for_waiter(
+                Some("test.wait_for_any_value"),
+                Duration::from_secs(10)
+            ));
+            mock::set("test.wait_for_any_value", "done");
+        });
+        let value = watcher.wait_for_any_value(&accepted, Some(Duration::from_secs(10))).unwrap();
+        handle.join().unwrap();
+        assert_eq!(value, "done");
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn raw_prop_info_test() {
+        let mut watcher = PropertyWatcher::new("test.raw_prop_info").unwrap();
+        // SAFETY: The pointer is only compared.
+        assert!(unsafe { watcher.raw_prop_info() }.is_none());
+        mock::set("test.raw_prop_info", "1");
+        let name = CString::new("test.raw_prop_info").unwrap();
+        // SAFETY: The pointer is only compared, and find is called with a valid C string.
+        unsafe {
+            assert_eq!(watcher.raw_prop_info(), Some(sys::__system_property_find(name.as_ptr())));
+        }
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn poll_test() {
+        let mut watcher = PropertyWatcher::new("test.poll").unwrap();
+        assert!(!watcher.poll().unwrap());
+        mock::set("test.poll", "1");
+        assert!(watcher.poll().unwrap());
+        assert!(!watcher.poll().unwrap());
+        mock::set("test.poll", "2");
+        assert!(watcher.poll().unwrap());
+        assert!(!watcher.poll().unwrap());
+        // The serial number is shared with the wait functions.
+        assert!(matches!(
+            watcher.wait(Some(Duration::from_millis(10))),
+            Err(PropertyWatcherError::Timeout { .. })
+        ));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_f64_test() {
+        assert_eq!(read_f64("test.read_f64").unwrap(), None);
+        mock::set("test.read_f64", "1.5");
+        assert_eq!(read_f64("test.read_f64").unwrap(), Some(1.5));
+        mock::set("test.read_f64", "-inf");
+        assert_eq!(read_f64("test.read_f64").unwrap(), Some(f64::NEG_INFINITY));
+        mock::set("test.read_f64", "NaN");
+        assert!(read_f64("test.read_f64").unwrap().unwrap().is_nan());
+        mock::set("test.read_f64", "1,5");
+        assert!(matches!(read_f64("test.read_f64"), Err(SysPropError::ParseError(_))));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_duration_test() {
+        let ms = Duration::from_millis(1);
+        assert_eq!(read_duration("test.read_duration", ms).unwrap(), None);
+        mock::set("test.read_duration", "5000");
+        assert_eq!(read_duration("test.read_duration", ms).unwrap(), Some(Duration::from_secs(5)));
+        mock::set("test.read_duration", "5s");
+        assert_eq!(read_duration("test.read_duration", ms).unwrap(), Some(Duration::from_secs(5)));
+        mock::set("test.read_duration", "5 seconds");
+        assert!(matches!(
+            read_duration("test.read_duration", ms),
+            Err(SysPropError::ParseError(_))
+        ));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_version_test() {
+        assert_eq!(read_version("test.read_version").unwrap(), None);
+        mock::set("test.read_version", "14.0.1");
+        let version = read_version("test.read_version").unwrap().unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (14, 0, 1));
+        mock::set("test.read_version", "UpsideDownCake");
+        assert!(matches!(read_version("test.read_version"), Err(SysPropError::ParseError(_))));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn try_write_test() {
+        assert!(try_write("test.try_write", "1"));
+        assert_eq!(read("test.try_write").unwrap().as_deref(), Some("1"));
+        assert!(!try_write("bad name", "1"));
+        assert!(!try_write("test.try_write", &"a".repeat(PROP_VALUE_MAX)));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_timeout_test() {
+        let timeout = Duration::from_secs(10);
+        assert_eq!(read_timeout("test.read_timeout", timeout).unwrap(), None);
+        mock::set("test.read_timeout", "1");
+        assert_eq!(read_timeout("test.read_timeout", timeout).unwrap().as_deref(), Some("1"));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn wait_for_delta_test() {
+        let mut watcher = PropertyWatcher::new("test.wait_for_delta").unwrap();
+        let timeout = Some(Duration::from_secs(10));
+
+        let handle = std::thread::spawn(|| {
+            // The property doesn't exist yet, so the watcher waits for any property to change.
+            assert!(mock::wait_for_waiter(None, Duration::from_secs(10)));
+            mock::set("test.wait_for_delta", "1");
+        });
+        let change = watcher.wait_for_delta(timeout).unwrap();
+        handle.join().unwrap();
+        assert_eq!(change, PropertyChange { old: None, new: Some("1".to_owned()) });
+
+        assert!(matches!(
+            watcher.wait_for_delta(Some(Duration::from_millis(10))),
+            Err(PropertyWatcherError::Timeout { .. })
+        ));
+
+        mock::set("test.wait_for_delta", "2");
+        let change = watcher.wait_for_delta(timeout).unwrap();
+        assert_eq!(change, PropertyChange { old: Some("1".to_owned()), new: Some("2".to_owned()) });
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_verified_test() {
+        write_verified("test.write_verified", "1").unwrap();
+        assert_eq!(read("test.write_verified").unwrap().as_deref(), Some("1"));
+        write_verified_with_timeout("test.write_verified", "2", Duration::ZERO).unwrap();
+        assert_eq!(read("test.write_verified").unwrap().as_deref(), Some("2"));
+        assert!(matches!(
+            write_verified("test.write_verified", &"a".repeat(PROP_VALUE_MAX)),
+            Err(PropertyWatcherError::ValueTooLong { .. })
+        ));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn set_and_confirm_test() {
+        set_and_confirm("test.set_and_confirm", "1", Some(Duration::from_secs(10))).unwrap();
+        assert_eq!(read("test.set_and_confirm").unwrap().as_deref(), Some("1"));
+        assert!(matches!(
+            set_and_confirm("bad name", "1", Some(Duration::from_secs(10))),
+            Err(PropertyWatcherError::IllegalName(_))
+        ));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn swap_test() {
+        assert_eq!(swap("test.swap", "1").unwrap(), None);
+        assert_eq!(swap("test.swap", "2").unwrap().as_deref(), Some("1"));
+        assert_eq!(read("test.swap").unwrap().as_deref(), Some("2"));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn is_available_test() {
+        assert!(is_available());
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn area_serial_test() {
+        let serial = area_serial();
+        mock::set("test.area_serial", "1");
+        assert_ne!(area_serial(), serial);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn exists_test() {
+        let mut watcher = PropertyWatcher::new("test.exists").unwrap();
+        assert!(!exists("test.exists").unwrap());
+        assert!(!watcher.exists());
+        mock::set("test.exists", "");
+        assert!(exists("test.exists").unwrap());
+        assert!(watcher.exists());
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn foreach_raw_test() {
+        mock::set("test.foreach_raw", "1");
+        let mut found = false;
+        foreach_raw(|name, value| {
+            found |= name == c"test.foreach_raw" && value == c"1";
+        })
+        .unwrap();
+        assert!(found);
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_if_equals_test() {
+        let prop = "test.write_if_equals";
+        // Other tests change the global serial number concurrently, so retry when contended.
+        let attempt = |expected, new_value| loop {
+            match write_if_equals(prop, expected, new_value) {
+                Err(PropertyWatcherError::Contended { .. }) => continue,
+                result => break result.unwrap(),
+            }
+        };
+        assert!(attempt(None, "a"));
+        assert!(!attempt(None, "b"));
+        assert!(!attempt(Some("b"), "c"));
+        assert!(attempt(Some("a"), "c"));
+        assert_eq!(read(prop).unwrap().as_deref(), Some("c"));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn write_if_equals_contended_test() {
+        let prop = "test.write_if_equals_contended";
+        mock::set(prop, "a");
+        // A global serial number that changes between every pair of samples.
+        let mut serial = 0;
+        let result = write_if_equals_with_serial(prop, Some("a"), "b", || {
+            serial += 1;
+            serial
+        });
+        assert!(
+            matches!(&result, Err(PropertyWatcherError::Contended { name }) if name == prop),
+            "testing with {:?}",
+            result
+        );
+        assert_eq!(serial, 2 * WRITE_IF_EQUALS_ATTEMPTS as u32);
+        assert_eq!(read(prop).unwrap().as_deref(), Some("a"));
+    }
 
     #[test]
     fn foreach_test() {
+        #[cfg(feature = "test-mock")]
+        mock::set("test.foreach", "1");
+
         let mut properties = Vec::new();
         assert!(foreach(|name, value| {
             properties.push((name.to_owned(), value.to_owned()));
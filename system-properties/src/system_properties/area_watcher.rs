@@ -0,0 +1,135 @@
+//! Watching for system properties being created or removed.
+
+use super::{
+    area_serial, deadline_after, foreach, remaining_time_until, sys, wait_error,
+    PropertyWatcherError, Result,
+};
+use std::collections::BTreeSet;
+use std::ptr::null;
+use std::time::Duration;
+
+/// The properties that were created or removed between two snapshots taken by an
+/// [`AreaWatcher`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AreaChanges {
+    /// Names of the properties that were created, in sorted order.
+    pub created: Vec<String>,
+    /// Names of the properties that were removed, in sorted order.
+    pub removed: Vec<String>,
+}
+
+impl AreaChanges {
+    /// Returns true if no property was created or removed.
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// AreaWatcher watches the whole system property area for properties being created or removed.
+///
+/// It keeps the names of all properties (that the current process is allowed to access). Whenever
+/// the area's serial number changes, which happens when any property is added or changed, it
+/// enumerates the properties again and compares the names to the previous snapshot. Changes to
+/// the values of existing properties aren't reported. Bionic never removes properties, so
+/// removals can only be observed with the `test-mock` feature or if access to a property is lost.
+pub struct AreaWatcher {
+    names: BTreeSet<String>,
+    serial: u32,
+}
+
+impl AreaWatcher {
+    /// Create an AreaWatcher with a snapshot of the current properties.
+    ///
+    /// Only properties created or removed after the AreaWatcher is created are reported.
+    pub fn new() -> Result<Self> {
+        // Snapshot the serial first so that properties created while enumerating aren't missed.
+        let serial = area_serial();
+        Ok(Self { names: snapshot_names()?, serial })
+    }
+
+    /// Returns the names of the properties in the latest snapshot, in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Waits until at least one property is created or removed, or the timeout elapses. Returns
+    /// the changes since the last call, or empty changes if the timeout elapsed.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<AreaChanges> {
+        let until = timeout.and_then(deadline_after);
+        loop {
+            let serial = area_serial();
+            if serial != self.serial {
+                let names = snapshot_names()?;
+                self.serial = serial;
+                let changes = AreaChanges {
+                    created: names.difference(&self.names).cloned().collect(),
+                    removed: self.names.difference(&names).cloned().collect(),
+                };
+                self.names = names;
+                if !changes.is_empty() {
+                    return Ok(changes);
+                }
+            }
+
+            let remaining_timeout = remaining_time_until(until);
+            let mut new_serial = self.serial;
+            // SAFETY: The function modifies only new_serial, and has no side-effects.
+            if !unsafe {
+                // Wait for the area's serial number to change, then take a new snapshot.
+                sys::__system_property_wait(
+                    null(),
+                    self.serial,
+                    &mut new_serial,
+                    if let Some(remaining_timeout) = &remaining_timeout {
+                        remaining_timeout
+                    } else {
+                        null()
+                    },
+                )
+            } {
+                return match wait_error(until, "<property area>".to_owned()) {
+                    PropertyWatcherError::Timeout { .. } => Ok(AreaChanges::default()),
+                    e => Err(e),
+                };
+            }
+        }
+    }
+}
+
+/// Returns the names of all properties that the current process is allowed to access.
+fn snapshot_names() -> Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+    foreach(|name, _| {
+        names.insert(name.to_owned());
+    })?;
+    Ok(names)
+}
+
+#[cfg(all(test, feature = "test-mock"))]
+mod test {
+    use super::super::mock;
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn wait_test() {
+        mock::set("area_watcher.test.existing", "1");
+        let mut watcher = AreaWatcher::new().unwrap();
+        assert!(watcher.names().any(|name| name == "area_watcher.test.existing"));
+
+        let handle = thread::spawn(|| {
+            assert!(mock::wait_for_waiter(None, Duration::from_secs(10)));
+            mock::set("area_watcher.test.existing", "2");
+            mock::set("area_watcher.test.created", "1");
+        });
+        // Other tests may create properties concurrently, so wait until ours shows up.
+        let mut created = Vec::new();
+        while !created.iter().any(|name| name == "area_watcher.test.created") {
+            let changes = watcher.wait(Some(Duration::from_secs(10))).unwrap();
+            assert!(!changes.is_empty());
+            assert!(!changes.created.iter().any(|name| name == "area_watcher.test.existing"));
+            created.extend(changes.created);
+        }
+        handle.join().unwrap();
+    }
+}
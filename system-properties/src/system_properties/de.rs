@@ -0,0 +1,249 @@
+//! Deserializing values from system properties.
+
+use super::{foreach_prefix, parsers_formatters, PropertyWatcherError, Result};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+impl de::Error for PropertyWatcherError {
+    fn custom<T: Display>(msg: T) -> Self {
+        PropertyWatcherError::DeserializeError(msg.to_string())
+    }
+}
+
+/// Deserializes a `T` from the system properties named `<prefix>.<field>`.
+///
+/// Each field of `T` is read from the property named after the field, so a field `timeout` with
+/// the prefix `persist.foo` is read from `persist.foo.timeout`. Values are converted with the
+/// functions in [`parsers_formatters`], so lists are comma-separated. Missing properties map to
+/// `None` for `Option` fields and can be given a default with `#[serde(default)]`. An empty
+/// property value is also treated as `None` for `Option` fields.
+pub fn deserialize_from_props<T: DeserializeOwned>(prefix: &str) -> Result<T> {
+    let prefix = format!("{prefix}.");
+    let mut properties = HashMap::new();
+    foreach_prefix(&prefix, |name, value| {
+        properties.insert(name[prefix.len()..].to_owned(), value.to_owned());
+    })?;
+    from_map(properties)
+}
+
+fn from_map<T: DeserializeOwned>(properties: HashMap<String, String>) -> Result<T> {
+    T::deserialize(PropertiesDeserializer(properties))
+}
+
+/// Deserializer for a struct or map whose fields are stored in separate properties.
+struct PropertiesDeserializer(HashMap<String, String>);
+
+impl<'de> de::Deserializer<'de> for PropertiesDeserializer {
+    type Error = PropertyWatcherError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(PropertiesMapAccess { iter: self.0.into_iter(), value: None })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+struct PropertiesMapAccess {
+    iter: std::collections::hash_map::IntoIter<String, String>,
+    value: Option<String>,
+}
+
+impl<'de> MapAccess<'de> for PropertiesMapAccess {
+    type Error = PropertyWatcherError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().ok_or_else(|| {
+            PropertyWatcherError::DeserializeError("value requested before key".to_owned())
+        })?;
+        seed.deserialize(ValueDeserializer(value))
+    }
+}
+
+/// Deserializer for the value of a single property.
+struct ValueDeserializer(String);
+
+impl ValueDeserializer {
+    fn parse<T: FromStr>(&self) -> Result<T> {
+        parsers_formatters::parse(&self.0).map_err(PropertyWatcherError::DeserializeError)
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = PropertyWatcherError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_bool(
+            parsers_formatters::parse_bool(&self.0)
+                .map_err(PropertyWatcherError::DeserializeError)?,
+        )
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let items = parsers_formatters::parse_list::<String>(&self.0)
+            .map_err(PropertyWatcherError::DeserializeError)?;
+        visitor.visit_seq(ListSeqAccess(items.into_iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Access to the elements of a comma-separated list property.
+struct ListSeqAccess(std::vec::IntoIter<String>);
+
+impl<'de> SeqAccess<'de> for ListSeqAccess {
+    type Error = PropertyWatcherError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        self.0.next().map(|item| seed.deserialize(ValueDeserializer(item))).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    enum Mode {
+        Fast,
+        Slow,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        enabled: bool,
+        count: u32,
+        ratio: f64,
+        name: String,
+        mode: Mode,
+        tags: Vec<String>,
+        limit: Option<u64>,
+        empty: Option<u64>,
+        #[serde(default)]
+        retries: u8,
+    }
+
+    fn props(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn from_map_test() {
+        let config: Config = from_map(props(&[
+            ("enabled", "true"),
+            ("count", "3"),
+            ("ratio", "0.5"),
+            ("name", "foo"),
+            ("mode", "slow"),
+            ("tags", "a,b\\,c"),
+            ("empty", ""),
+            ("unknown", "ignored"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                enabled: true,
+                count: 3,
+                ratio: 0.5,
+                name: "foo".to_owned(),
+                mode: Mode::Slow,
+                tags: vec!["a".to_owned(), "b,c".to_owned()],
+                limit: None,
+                empty: None,
+                retries: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn from_map_invalid_test() {
+        let result: Result<Config> = from_map(props(&[("enabled", "yes")]));
+        assert!(matches!(result, Err(PropertyWatcherError::DeserializeError(_))));
+    }
+}
@@ -18,20 +18,60 @@ use std::str::Utf8Error;
 use thiserror::Error;
 
 /// Errors this crate can generate
+///
+/// New variants may be added in minor releases.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum PropertyWatcherError {
     /// We can't watch for a property whose name contains a NUL character.
-    #[error("Cannot convert name to C string")]
-    BadNameError(#[from] std::ffi::NulError),
+    #[error("System property name {name:?} contains a NUL character at byte {position}")]
+    BadNameError {
+        /// Name of the property.
+        name: String,
+        /// Byte offset of the first NUL character in the name.
+        position: usize,
+    },
+    /// We can't write a value that contains a NUL character.
+    #[error("Value for system property {name:?} contains a NUL character at byte {position}")]
+    BadValueError {
+        /// Name of the property.
+        name: String,
+        /// Byte offset of the first NUL character in the value.
+        position: usize,
+    },
     /// We can only watch for properties that exist when the watcher is created.
-    #[error("System property is absent")]
-    SystemPropertyAbsent,
+    #[error("System property {name:?} is absent")]
+    SystemPropertyAbsent {
+        /// Name of the property.
+        name: String,
+    },
     /// System properties are not initialized
-    #[error("System properties are not initialized.")]
-    Uninitialized,
-    /// __system_property_wait timed out.
-    #[error("Wait failed")]
-    WaitFailed,
+    ///
+    /// This is reported when `__system_property_foreach` fails, which bionic only does when the
+    /// property area couldn't be mapped, such as when the process isn't running on Android.
+    #[error("System properties are not initialized{}", describe_errno(*errno))]
+    Uninitialized {
+        /// The errno value after the failed call, or 0 if none was reported.
+        errno: i32,
+    },
+    /// __system_property_wait failed.
+    #[error("Wait for system property {name:?} failed")]
+    WaitFailed {
+        /// Name of the property.
+        name: String,
+    },
+    /// The deadline passed before the property was created or changed.
+    #[error("Wait for system property {name:?} timed out")]
+    Timeout {
+        /// Name of the property.
+        name: String,
+    },
+    /// The wait was cancelled before the property changed.
+    #[error("Wait for system property {name:?} was cancelled")]
+    Cancelled {
+        /// Name of the property.
+        name: String,
+    },
     /// read callback was not called
     #[error("__system_property_read_callback did not call callback")]
     ReadCallbackNotCalled,
@@ -42,11 +82,91 @@ pub enum PropertyWatcherError {
     #[error("__system_property_read_callback gave us a non-UTF8 C string")]
     BadCString(#[from] Utf8Error),
     /// read callback returned an error
-    #[error("Callback failed")]
-    CallbackError(#[from] anyhow::Error),
+    ///
+    /// The message includes the error's whole context chain, so the error is deliberately not
+    /// exposed as the [`source`](std::error::Error::source), which would make reporters that walk
+    /// the chain print it twice.
+    #[error("Callback failed: {0:#}")]
+    CallbackError(anyhow::Error),
     /// Failure in setting the system property
-    #[error("__system_property_set failed.")]
-    SetPropertyFailed,
+    #[error("__system_property_set failed{}", describe_errno(*errno))]
+    SetPropertyFailed {
+        /// The errno value after the failed call, or 0 if none was reported.
+        errno: i32,
+    },
+    /// The name is not a legal system property name
+    #[error("Illegal system property name: {0:?}")]
+    IllegalName(String),
+    /// The name is too long for this device
+    #[error("Name is {len} bytes long, but the maximum is {max} bytes")]
+    NameTooLong {
+        /// Length of the name in bytes.
+        len: usize,
+        /// Maximum allowed length in bytes.
+        max: usize,
+    },
+    /// The value is too long to be written to the system property
+    #[error("Value is {len} bytes long, but the maximum is {max} bytes")]
+    ValueTooLong {
+        /// Length of the value in bytes.
+        len: usize,
+        /// Maximum allowed length in bytes.
+        max: usize,
+    },
+    /// The value read back after a write differs from the one written
+    #[error("System property {name:?} was set to {expected:?}, but reads back as {actual:?}")]
+    WriteMismatch {
+        /// Name of the property.
+        name: String,
+        /// The value that was written.
+        expected: String,
+        /// The value that was read back, or `None` if the property doesn't exist.
+        actual: Option<String>,
+    },
+    /// Other properties kept changing while a conditional write was checking the current value
+    #[error("System property {name:?} couldn't be compared because properties kept changing")]
+    Contended {
+        /// Name of the property.
+        name: String,
+    },
+    /// The property can't be cached because it isn't read-only
+    #[cfg(feature = "ro-cache")]
+    #[error("System property {0:?} is not read-only")]
+    NotReadOnly(String),
+    /// The JSON for importing system properties is invalid
+    #[cfg(feature = "json")]
+    #[error("Invalid JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+    /// A read-only property changed after it was first read
+    #[cfg(feature = "ro-cache")]
+    #[error("Read-only system property {name:?} changed from {first:?} to {current:?}")]
+    ReadOnlyChanged {
+        /// Name of the property.
+        name: String,
+        /// The first value that was read.
+        first: String,
+        /// The current value, or `None` if the property no longer exists.
+        current: Option<String>,
+    },
+    /// Failed to deserialize system properties into a value
+    #[cfg(feature = "serde")]
+    #[error("Failed to deserialize system properties: {0}")]
+    DeserializeError(String),
+}
+
+impl From<anyhow::Error> for PropertyWatcherError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::CallbackError(error)
+    }
+}
+
+/// Formats an errno value for inclusion in an error message.
+fn describe_errno(errno: i32) -> String {
+    if errno == 0 {
+        ".".to_owned()
+    } else {
+        format!(": {}", std::io::Error::from_raw_os_error(errno))
+    }
 }
 
 /// Result type specific for this crate.
@@ -0,0 +1,90 @@
+//! Exporting system properties to JSON and importing them back.
+//!
+//! The JSON is an object mapping each property name to its value as a string, with the names in
+//! sorted order, so that exports of different devices or boots can be diffed directly.
+
+use super::{foreach, is_read_only, write, Result};
+use std::collections::BTreeMap;
+
+/// Returns the properties (that the current process is allowed to access) as a pretty-printed
+/// JSON object, sorted by name.
+pub fn export_json() -> Result<String> {
+    let mut properties = BTreeMap::new();
+    foreach(|name, value| {
+        properties.insert(name.to_owned(), value.to_owned());
+    })?;
+    Ok(serde_json::to_string_pretty(&properties)?)
+}
+
+/// Writes each property in the JSON object `json`, in sorted order, and returns the result of
+/// every write.
+///
+/// If `skip_ro` is true, read-only properties (see [`is_read_only`]) aren't written and don't
+/// appear in the results. This is usually needed when importing an export from a running device,
+/// since read-only properties that are already set can't be changed. A failed write doesn't stop
+/// the import, so the results should be checked. Returns
+/// [`PropertyWatcherError::JsonError`](super::PropertyWatcherError::JsonError) without writing
+/// anything if `json` isn't an object whose values are all strings.
+pub fn import_json(json: &str, skip_ro: bool) -> Result<Vec<(String, Result<()>)>> {
+    let properties: BTreeMap<String, String> = serde_json::from_str(json)?;
+    Ok(properties
+        .into_iter()
+        .filter(|(name, _)| !skip_ro || !is_read_only(name))
+        .map(|(name, value)| {
+            let result = write(&name, &value);
+            (name, result)
+        })
+        .collect())
+}
+
+#[cfg(all(test, feature = "test-mock"))]
+mod test {
+    use super::super::{mock, read, PropertyWatcherError};
+    use super::*;
+
+    #[test]
+    fn export_test() {
+        mock::set("json.test.export.b", "2");
+        mock::set("json.test.export.a", "1 \"quoted\"");
+        let json = export_json().unwrap();
+        let a = json.find(r#""json.test.export.a": "1 \"quoted\"""#).unwrap();
+        let b = json.find(r#""json.test.export.b": "2""#).unwrap();
+        assert!(a < b, "testing with {}", json);
+    }
+
+    #[test]
+    fn import_test() {
+        mock::set("ro.json.test.import", "old");
+        let json = r#"{
+            "json.test.import.b": "2",
+            "json.test.import.a": "1",
+            "ro.json.test.import": "new",
+            "bad name": "3"
+        }"#;
+
+        let results = import_json(json, true).unwrap();
+        let names = results.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["bad name", "json.test.import.a", "json.test.import.b"]);
+        assert!(matches!(results[0].1, Err(PropertyWatcherError::IllegalName(_))));
+        assert!(results[1].1.is_ok() && results[2].1.is_ok());
+        assert_eq!(read("json.test.import.a").unwrap().as_deref(), Some("1"));
+        assert_eq!(read("ro.json.test.import").unwrap().as_deref(), Some("old"));
+
+        let results = import_json(json, false).unwrap();
+        let (name, result) = &results[3];
+        assert_eq!(name, "ro.json.test.import");
+        assert!(matches!(result, Err(PropertyWatcherError::SetPropertyFailed { .. })));
+    }
+
+    #[test]
+    fn import_invalid_test() {
+        for json in ["", "[]", r#"{"json.test.import_invalid": 1}"#] {
+            assert!(
+                matches!(import_json(json, true), Err(PropertyWatcherError::JsonError(_))),
+                "testing with {}",
+                json
+            );
+        }
+        assert_eq!(read("json.test.import_invalid").unwrap(), None);
+    }
+}
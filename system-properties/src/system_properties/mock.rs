@@ -0,0 +1,557 @@
+//! In-process system property store for testing off-device.
+//!
+//! When the `test-mock` feature is enabled, the `__system_property_*` functions used by this
+//! crate can operate on a process-wide, in-memory store instead of bionic's property area. The
+//! store starts out empty and can be seeded with [`set`], or from a directory of `.prop` files
+//! with [`with_property_area`]. Changes made with [`set`] wake up any thread waiting for the
+//! property to change, just like on a device.
+//!
+//! Off-device, where there is no property area, the mock store is always used. On a device, the
+//! real property area is used unless [`enable`] is called first, so that a dependency enabling
+//! the feature can't redirect the reads and writes of a production build. This crate's own tests
+//! always use the mock store.
+//!
+//! Bionic always maps its property area from `/dev/__properties__`. The only way of pointing a
+//! process at a different area is `__system_property_set_filename`, which is a platform-only
+//! function that isn't part of the NDK, so fixtures can only be used with this mock backend.
+
+use std::collections::BTreeMap;
+use std::ffi::{CString, NulError};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+// Which backend the `__system_property_*` functions use. Once a backend is used, it can't be
+// switched, since handles from one can't be passed to the other.
+const UNDECIDED: u8 = 0;
+const REAL: u8 = 1;
+const MOCK: u8 = 2;
+
+static BACKEND: AtomicU8 =
+    AtomicU8::new(if cfg!(any(test, not(target_os = "android"))) { MOCK } else { UNDECIDED });
+
+/// Makes this crate use the mock store instead of bionic's property area for the rest of the
+/// process.
+///
+/// Off-device, the mock store is always used and this does nothing. On a device, this must be
+/// called before any property is accessed through this crate.
+///
+/// # Panics
+///
+/// Panics if a property was already accessed through bionic's property area.
+pub fn enable() {
+    let result = BACKEND.compare_exchange(UNDECIDED, MOCK, Ordering::Relaxed, Ordering::Relaxed);
+    if result == Err(REAL) {
+        panic!("bionic's property area is already in use");
+    }
+}
+
+/// Returns whether this crate uses the mock store. See [`enable`].
+pub fn is_enabled() -> bool {
+    BACKEND.load(Ordering::Relaxed) == MOCK
+}
+
+/// Returns whether the mock store should be used for a call, settling on bionic's property area
+/// if [`enable`] hasn't been called.
+pub(super) fn is_active() -> bool {
+    match BACKEND.compare_exchange(UNDECIDED, REAL, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => false,
+        Err(backend) => backend == MOCK,
+    }
+}
+
+struct Entry {
+    info: &'static ffi::MockPropInfo,
+    value: CString,
+    serial: u32,
+}
+
+struct Store {
+    properties: BTreeMap<CString, Entry>,
+    serial: u32,
+    // Number of threads blocked in `__system_property_wait`, by property name, or `None` for
+    // threads waiting for any property to change.
+    waiters: BTreeMap<Option<CString>, usize>,
+}
+
+static STORE: Mutex<Store> =
+    Mutex::new(Store { properties: BTreeMap::new(), serial: 0, waiters: BTreeMap::new() });
+static CHANGED: Condvar = Condvar::new();
+static WAITERS_CHANGED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Store> {
+    STORE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Store {
+    fn set(&mut self, name: CString, value: CString) {
+        self.serial = self.serial.wrapping_add(1);
+        match self.properties.get_mut(&name) {
+            Some(entry) => {
+                entry.value = value;
+                entry.serial = entry.serial.wrapping_add(1);
+            }
+            None => {
+                // Like in bionic, a prop_info is never freed once created.
+                let info = Box::leak(Box::new(ffi::MockPropInfo { name: name.clone() }));
+                self.properties.insert(name, Entry { info, value, serial: 1 });
+            }
+        }
+        CHANGED.notify_all();
+    }
+
+    fn remove(&mut self, name: &CString) {
+        if self.properties.remove(name).is_some() {
+            self.serial = self.serial.wrapping_add(1);
+            CHANGED.notify_all();
+        }
+    }
+}
+
+/// Sets a property in the mock store, bypassing the checks done by `__system_property_set`.
+///
+/// On a device, the store is only read by this crate after calling [`enable`].
+///
+/// # Panics
+///
+/// Panics if `name` or `value` contains a NUL character.
+pub fn set(name: &str, value: &str) {
+    let name = CString::new(name).expect("name contains NUL");
+    let value = CString::new(value).expect("value contains NUL");
+    lock().set(name, value);
+}
+
+/// Sets a property in the mock store to a value that may not be valid UTF-8.
+///
+/// # Panics
+///
+/// Panics if `name` or `value` contains a NUL character.
+pub fn set_bytes(name: &str, value: &[u8]) {
+    let name = CString::new(name).expect("name contains NUL");
+    let value = CString::new(value).expect("value contains NUL");
+    lock().set(name, value);
+}
+
+/// Parses the contents of a `.prop` file, like `build.prop`, into name and value pairs.
+///
+/// Like init, this ignores blank lines, comments starting with `#`, and lines without `=`, and
+/// trims whitespace around names and values.
+fn parse_prop_file(contents: &str) -> Vec<(&str, &str)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+// Undoes the changes made by `with_property_area`, even if the closure panics.
+struct AreaGuard {
+    // The previous values of the properties set from the fixture, or `None` for properties that
+    // didn't exist before.
+    previous: BTreeMap<CString, Option<CString>>,
+}
+
+impl Drop for AreaGuard {
+    fn drop(&mut self) {
+        let mut store = lock();
+        for (name, value) in std::mem::take(&mut self.previous) {
+            match value {
+                Some(value) => store.set(name, value),
+                None => store.remove(&name),
+            }
+        }
+    }
+}
+
+/// Loads the properties from every `*.prop` file in `dir` into the mock store, calls `f`, and then
+/// restores the properties that were loaded to their previous values, or removes them if they
+/// didn't exist before.
+///
+/// This makes it possible to run tests against a fixture property area, such as one captured
+/// from a device with `adb shell getprop` converted to `name=value` lines, or a `build.prop`. The
+/// files are loaded in order of their names and later values override earlier ones. Properties
+/// that aren't in the fixture are left alone, so they remain visible to `f`, and changes that
+/// `f` makes to them aren't undone.
+///
+/// This only affects the mock store. Bionic has no supported way of loading a different property
+/// area on a device (see the [module documentation](self)).
+///
+/// # Errors
+///
+/// Returns an error if `dir` or one of the files can't be read, or if a name or value in the
+/// files contains a NUL character, in which case `f` isn't called and the store isn't changed.
+pub fn with_property_area<T>(dir: &Path, f: impl FnOnce() -> T) -> io::Result<T> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "prop") && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let contents = paths.iter().map(fs::read_to_string).collect::<io::Result<Vec<_>>>()?;
+    let properties = contents
+        .iter()
+        .flat_map(|c| parse_prop_file(c))
+        .map(|(name, value)| Ok((CString::new(name)?, CString::new(value)?)))
+        .collect::<Result<Vec<_>, NulError>>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut guard = AreaGuard { previous: BTreeMap::new() };
+    {
+        let mut store = lock();
+        for (name, value) in properties {
+            if !guard.previous.contains_key(&name) {
+                let previous = store.properties.get(&name).map(|entry| entry.value.clone());
+                guard.previous.insert(name.clone(), previous);
+            }
+            store.set(name, value);
+        }
+    }
+
+    Ok(f())
+}
+
+/// Blocks until at least one thread is waiting for the property `name` to change, or for any
+/// property to change if `name` is `None`, or until the timeout elapses. Returns whether a thread
+/// is waiting.
+///
+/// This lets a test change a property only once another thread is blocked waiting for it, without
+/// sleeping for an arbitrary amount of time. Threads waiting for a property to be created wait for
+/// any property to change.
+///
+/// # Panics
+///
+/// Panics if `name` contains a NUL character.
+pub fn wait_for_waiter(name: Option<&str>, timeout: Duration) -> bool {
+    let name = name.map(|name| CString::new(name).expect("name contains NUL"));
+    let store = lock();
+    let (store, _) = WAITERS_CHANGED
+        .wait_timeout_while(store, timeout, |store| !store.waiters.contains_key(&name))
+        .unwrap_or_else(|e| e.into_inner());
+    store.waiters.contains_key(&name)
+}
+
+/// Removes all properties from the mock store.
+///
+/// Existing [`PropertyWatcher`](super::PropertyWatcher)s for removed properties will fail to read
+/// until the property is set again.
+pub fn clear() {
+    let mut store = lock();
+    store.properties.clear();
+    store.serial = store.serial.wrapping_add(1);
+    CHANGED.notify_all();
+}
+
+/// Replacements for the bindgen-generated FFI functions.
+///
+/// The `prop_info` handles they return point to a [`MockPropInfo`], and they must only be passed
+/// back to these functions.
+pub(super) mod ffi {
+    use super::super::sys::prop_info;
+    use super::super::PROP_VALUE_MAX;
+    use super::{lock, CHANGED, WAITERS_CHANGED};
+    use libc::timespec;
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+    use std::ptr::null;
+    use std::time::{Duration, Instant};
+
+    /// Mock equivalent of bionic's opaque `prop_info`.
+    pub struct MockPropInfo {
+        pub(super) name: CString,
+    }
+
+    impl MockPropInfo {
+        fn as_ptr(&'static self) -> *const prop_info {
+            (self as *const Self).cast()
+        }
+
+        /// # Safety
+        ///
+        /// `pi` must be a handle returned by [`__system_property_find`].
+        unsafe fn from_ptr(pi: *const prop_info) -> &'static Self {
+            // SAFETY: The handles point to leaked MockPropInfos.
+            unsafe { &*pi.cast::<Self>() }
+        }
+    }
+
+    pub unsafe fn __system_property_area_serial() -> u32 {
+        lock().serial
+    }
+
+    pub unsafe fn __system_property_find(name: *const c_char) -> *const prop_info {
+        // SAFETY: The caller passes a valid C string.
+        let name = unsafe { CStr::from_ptr(name) };
+        lock().properties.get(name).map_or(null(), |entry| entry.info.as_ptr())
+    }
+
+    pub unsafe fn __system_property_read_callback(
+        pi: *const prop_info,
+        callback: Option<unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char, u32)>,
+        cookie: *mut c_void,
+    ) {
+        // SAFETY: The caller passes a pointer returned by __system_property_find.
+        let pi = unsafe { MockPropInfo::from_ptr(pi) };
+        // Copy the value so that the lock isn't held while the callback runs.
+        let Some((value, serial)) =
+            lock().properties.get(&pi.name).map(|entry| (entry.value.clone(), entry.serial))
+        else {
+            return;
+        };
+        if let Some(callback) = callback {
+            // SAFETY: The strings outlive the call and the cookie is passed through unchanged.
+            unsafe { callback(cookie, pi.name.as_ptr(), value.as_ptr(), serial) };
+        }
+    }
+
+    pub unsafe fn __system_property_foreach(
+        callback: Option<unsafe extern "C" fn(*const prop_info, *mut c_void)>,
+        cookie: *mut c_void,
+    ) -> c_int {
+        let infos = lock().properties.values().map(|entry| entry.info).collect::<Vec<_>>();
+        if let Some(callback) = callback {
+            for info in infos {
+                // SAFETY: The prop_info is never freed and the cookie is passed through unchanged.
+                unsafe { callback(info.as_ptr(), cookie) };
+            }
+        }
+        0
+    }
+
+    pub unsafe fn __system_property_serial(pi: *const prop_info) -> u32 {
+        // SAFETY: The caller passes a pointer returned by __system_property_find.
+        let pi = unsafe { MockPropInfo::from_ptr(pi) };
+        lock().properties.get(&pi.name).map_or(0, |entry| entry.serial)
+    }
+
+    pub unsafe fn __system_property_set(name: *const c_char, value: *const c_char) -> c_int {
+        // SAFETY: The caller passes valid C strings.
+        let (name, value) = unsafe { (CStr::from_ptr(name), CStr::from_ptr(value)) };
+        let mut store = lock();
+        let read_only = name.to_bytes().starts_with(b"ro.");
+        // Like bionic, only allow long values for read-only properties.
+        if value.to_bytes().len() >= PROP_VALUE_MAX && !read_only {
+            return -1;
+        }
+        // Like init, refuse to change read-only properties once they're set.
+        if read_only && store.properties.contains_key(name) {
+            return -1;
+        }
+        store.set(name.to_owned(), value.to_owned());
+        0
+    }
+
+    pub unsafe fn __system_property_wait(
+        pi: *const prop_info,
+        old_serial: u32,
+        new_serial_ptr: *mut u32,
+        relative_timeout: *const timespec,
+    ) -> bool {
+        // SAFETY: The caller passes either null or a pointer returned by __system_property_find.
+        let name = (!pi.is_null()).then(|| unsafe { &MockPropInfo::from_ptr(pi).name });
+        // SAFETY: The caller passes either null or a valid timespec.
+        let timeout = unsafe { relative_timeout.as_ref() }
+            .map(|t| Duration::new(t.tv_sec as u64, t.tv_nsec as u32));
+        // A timeout too long to be represented is treated as infinite.
+        let until = timeout.and_then(|t| Instant::now().checked_add(t));
+
+        let key = name.cloned();
+        let mut store = lock();
+        *store.waiters.entry(key.clone()).or_default() += 1;
+        WAITERS_CHANGED.notify_all();
+        let changed = loop {
+            let serial = match name {
+                Some(name) => store.properties.get(name).map_or(0, |entry| entry.serial),
+                None => store.serial,
+            };
+            if serial != old_serial {
+                // SAFETY: The caller passes a valid pointer.
+                unsafe { *new_serial_ptr = serial };
+                break true;
+            }
+
+            store = match until {
+                Some(until) => {
+                    let Some(remaining) = until.checked_duration_since(Instant::now()) else {
+                        break false;
+                    };
+                    CHANGED.wait_timeout(store, remaining).unwrap_or_else(|e| e.into_inner()).0
+                }
+                None => CHANGED.wait(store).unwrap_or_else(|e| e.into_inner()),
+            };
+        };
+
+        if let Some(count) = store.waiters.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                store.waiters.remove(&key);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{
+        foreach, read, write, PropertyWatcher, PropertyWatcherError, PROP_VALUE_MAX,
+    };
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn enable_test() {
+        // Tests always use the mock store, so enabling it again does nothing.
+        assert!(is_enabled());
+        enable();
+        assert!(is_active());
+    }
+
+    #[test]
+    fn read_write_test() {
+        assert!(matches!(read("mock.test.read_write"), Ok(None)));
+        set("mock.test.read_write", "foo");
+        assert_eq!(read("mock.test.read_write").unwrap().as_deref(), Some("foo"));
+        write("mock.test.read_write", "bar").unwrap();
+        assert_eq!(read("mock.test.read_write").unwrap().as_deref(), Some("bar"));
+    }
+
+    #[test]
+    fn value_too_long_test() {
+        let long = "a".repeat(PROP_VALUE_MAX);
+        assert!(matches!(
+            write("mock.test.value_too_long", &long),
+            Err(PropertyWatcherError::ValueTooLong { len, max }) if len == PROP_VALUE_MAX && max == PROP_VALUE_MAX - 1
+        ));
+        write("mock.test.value_too_long", &long[1..]).unwrap();
+        write("ro.mock.test.value_too_long", &long).unwrap();
+    }
+
+    #[test]
+    fn read_only_test() {
+        set("ro.mock.test.read_only", "foo");
+        assert!(matches!(
+            write("ro.mock.test.read_only", "bar"),
+            Err(PropertyWatcherError::SetPropertyFailed { .. })
+        ));
+        assert_eq!(read("ro.mock.test.read_only").unwrap().as_deref(), Some("foo"));
+    }
+
+    #[test]
+    fn foreach_test() {
+        set("mock.test.foreach", "foo");
+        let mut found = false;
+        foreach(|name, value| found |= name == "mock.test.foreach" && value == "foo").unwrap();
+        assert!(found);
+    }
+
+    #[test]
+    fn foreach_non_utf8_test() {
+        set_bytes("mock.test.foreach_non_utf8", b"foo\xffbar");
+        let mut found = None;
+        foreach(|name, value| {
+            if name == "mock.test.foreach_non_utf8" {
+                found = Some(value.to_owned());
+            }
+        })
+        .unwrap();
+        assert_eq!(found.as_deref(), Some("foo\u{fffd}bar"));
+    }
+
+    #[test]
+    fn wait_test() {
+        set("mock.test.wait", "foo");
+        let mut watcher = PropertyWatcher::new("mock.test.wait").unwrap();
+        // Resolve the property so that the first wait doesn't just wait for it to be created.
+        watcher.current().unwrap();
+        // The first wait returns immediately since the watcher hasn't seen any serial yet.
+        watcher.wait(None).unwrap();
+
+        let handle = thread::spawn(|| {
+            assert!(wait_for_waiter(Some("mock.test.wait"), Duration::from_secs(10)));
+            set("mock.test.wait", "bar");
+        });
+        watcher.wait(Some(Duration::from_secs(10))).unwrap();
+        handle.join().unwrap();
+        assert_eq!(watcher.read(|_, value| Ok(value.to_owned())).unwrap(), "bar");
+    }
+
+    #[test]
+    fn wait_for_waiter_test() {
+        assert!(!wait_for_waiter(Some("mock.test.wait_for_waiter"), Duration::from_millis(10)));
+        set("mock.test.wait_for_waiter", "1");
+        let mut watcher = PropertyWatcher::new("mock.test.wait_for_waiter").unwrap();
+        watcher.current().unwrap();
+        watcher.wait(None).unwrap();
+        thread::scope(|s| {
+            s.spawn(|| {
+                assert!(wait_for_waiter(
+                    Some("mock.test.wait_for_waiter"),
+                    Duration::from_secs(10)
+                ));
+                set("mock.test.wait_for_waiter", "2");
+            });
+            watcher.wait(Some(Duration::from_secs(10))).unwrap();
+        });
+        assert!(!wait_for_waiter(Some("mock.test.wait_for_waiter"), Duration::ZERO));
+    }
+
+    #[test]
+    fn parse_prop_file_test() {
+        let contents = "# comment\n\nro.a=1\n  b.c = two words \nimport /foo.prop\nd=\n=e\nf=g=h\n";
+        assert_eq!(
+            parse_prop_file(contents),
+            [("ro.a", "1"), ("b.c", "two words"), ("d", ""), ("f", "g=h")]
+        );
+    }
+
+    #[test]
+    fn with_property_area_test() {
+        let dir = std::env::temp_dir().join(format!("mock-area-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1.prop"), "mock.test.area.a=1\nmock.test.area.b=1\n").unwrap();
+        fs::write(dir.join("2.prop"), "mock.test.area.b=2\n").unwrap();
+        fs::write(dir.join("ignored.txt"), "mock.test.area.c=1\n").unwrap();
+        set("mock.test.area.a", "old");
+
+        let result = with_property_area(&dir, || {
+            assert_eq!(read("mock.test.area.a").unwrap().as_deref(), Some("1"));
+            assert_eq!(read("mock.test.area.b").unwrap().as_deref(), Some("2"));
+            assert_eq!(read("mock.test.area.c").unwrap(), None);
+            42
+        });
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(read("mock.test.area.a").unwrap().as_deref(), Some("old"));
+        assert_eq!(read("mock.test.area.b").unwrap(), None);
+
+        assert!(with_property_area(&dir, || ()).is_err());
+
+        let dir = std::env::temp_dir().join(format!("mock-area-nul-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1.prop"), "mock.test.area_nul.a=1\nmock.test.area_nul.b=\0\n").unwrap();
+        let result = with_property_area(&dir, || ());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read("mock.test.area_nul.a").unwrap(), None);
+    }
+
+    #[test]
+    fn wait_for_creation_test() {
+        let handle = thread::spawn(|| {
+            // Waiting for a property to be created waits for any property to change.
+            assert!(wait_for_waiter(None, Duration::from_secs(10)));
+            set("mock.test.wait_for_creation", "foo");
+        });
+        let mut watcher = PropertyWatcher::new("mock.test.wait_for_creation").unwrap();
+        watcher.wait_for_value("foo", Some(Duration::from_secs(10))).unwrap();
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Watching several system properties with a single blocking call.
+
+use super::{
+    area_serial, deadline_after, remaining_time_until, sys, wait_error, PropertyWatcher,
+    PropertyWatcherError, Result,
+};
+use std::ptr::null;
+use std::time::Duration;
+
+/// MultiWatcher watches several system properties for changes using a single thread. It waits
+/// for the global serial number to change, which happens whenever any property changes, and then
+/// checks which of its properties changed.
+pub struct MultiWatcher {
+    // Each watcher is paired with the serial number of the last reported value, or `None` if the
+    // property didn't exist.
+    watchers: Vec<(PropertyWatcher, Option<u32>)>,
+    global_serial: u32,
+}
+
+impl MultiWatcher {
+    /// Create a MultiWatcher for the named system properties.
+    ///
+    /// Only changes made after the MultiWatcher is created are reported.
+    pub fn new(names: &[&str]) -> Result<Self> {
+        // Snapshot the global serial first so that changes made while the properties' serial
+        // numbers are read aren't missed.
+        let global_serial = area_serial();
+        let mut watchers = Vec::with_capacity(names.len());
+        for name in names {
+            let mut watcher = PropertyWatcher::new(name)?;
+            let serial = watcher.read_with_serial()?.map(|(_, serial)| serial);
+            watchers.push((watcher, serial));
+        }
+        Ok(Self { watchers, global_serial })
+    }
+
+    /// Returns the names of the watched properties, separated by commas, for use in error
+    /// messages.
+    fn names(&self) -> String {
+        let names = self.watchers.iter().map(|(w, _)| w.name()).collect::<Vec<_>>();
+        names.join(", ")
+    }
+
+    /// Returns the names and new values of the properties that changed since they were last
+    /// reported.
+    fn changes(&mut self) -> Result<Vec<(String, String)>> {
+        let mut changes = Vec::new();
+        for (watcher, last_serial) in &mut self.watchers {
+            if let Some((value, serial)) = watcher.read_with_serial()? {
+                if *last_serial != Some(serial) {
+                    *last_serial = Some(serial);
+                    changes.push((watcher.name().to_owned(), value));
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Waits until at least one of the watched properties changes or is created, or the timeout
+    /// elapses. Returns the names and new values of the properties that changed since the last
+    /// call, or an empty list if the timeout elapsed.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<(String, String)>> {
+        let until = timeout.and_then(deadline_after);
+        loop {
+            let changes = self.changes()?;
+            if !changes.is_empty() {
+                return Ok(changes);
+            }
+
+            let remaining_timeout = remaining_time_until(until);
+            let mut new_serial = self.global_serial;
+            // SAFETY: The function modifies only new_serial, and has no side-effects.
+            if !unsafe {
+                // Wait for a global serial number change, then check the properties again.
+                sys::__system_property_wait(
+                    null(),
+                    self.global_serial,
+                    &mut new_serial,
+                    if let Some(remaining_timeout) = &remaining_timeout {
+                        remaining_timeout
+                    } else {
+                        null()
+                    },
+                )
+            } {
+                return match wait_error(until, self.names()) {
+                    PropertyWatcherError::Timeout { .. } => Ok(Vec::new()),
+                    e => Err(e),
+                };
+            }
+            self.global_serial = new_serial;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-mock"))]
+mod test {
+    use super::super::mock;
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn wait_test() {
+        mock::set("multi_watcher.test.a", "1");
+        let mut watcher =
+            MultiWatcher::new(&["multi_watcher.test.a", "multi_watcher.test.b"]).unwrap();
+        assert!(watcher.wait(Some(Duration::from_millis(10))).unwrap().is_empty());
+
+        let handle = thread::spawn(|| {
+            assert!(mock::wait_for_waiter(None, Duration::from_secs(10)));
+            mock::set("multi_watcher.test.b", "2");
+        });
+        let changes = watcher.wait(Some(Duration::from_secs(10))).unwrap();
+        handle.join().unwrap();
+        assert_eq!(changes, [("multi_watcher.test.b".to_owned(), "2".to_owned())]);
+
+        mock::set("multi_watcher.test.a", "3");
+        let changes = watcher.wait(Some(Duration::from_secs(10))).unwrap();
+        assert_eq!(changes, [("multi_watcher.test.a".to_owned(), "3".to_owned())]);
+    }
+}
@@ -0,0 +1,137 @@
+//! Observing accesses to system properties.
+//!
+//! An observer set with [`set_observer`] is called for every read, write, and wait done through
+//! this crate, which makes it a single integration point for telemetry, such as counting accesses
+//! per property. This module is only available with the `observability` feature, so there is no
+//! overhead when it isn't enabled.
+
+use std::sync::{Arc, RwLock};
+
+/// An access to a system property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Access<'a> {
+    /// The value of the property was read.
+    Read {
+        /// Name of the property.
+        name: &'a str,
+    },
+    /// The property was written.
+    Write {
+        /// Name of the property.
+        name: &'a str,
+    },
+    /// A wait for the property to change or reach a value started.
+    Wait {
+        /// Name of the property.
+        name: &'a str,
+    },
+}
+
+type Observer = Arc<dyn Fn(Access<'_>) + Send + Sync>;
+
+static OBSERVER: RwLock<Option<Observer>> = RwLock::new(None);
+
+/// Sets the function called for every access to a system property, replacing any previous one.
+///
+/// The observer is called synchronously on the thread doing the access, before the access is
+/// made, so it should be cheap. A read is reported each time a value is read, including the reads
+/// done internally by functions like [`PropertyWatcher::wait_for_value`]. A wait is reported once
+/// per call to a wait function, not for each underlying `__system_property_wait` call.
+///
+/// The observer may access properties through this crate, which reports those accesses to the
+/// observer again, so it must take care not to recurse indefinitely. It may also call
+/// [`set_observer`] or [`clear_observer`], which only affect later accesses.
+///
+/// [`PropertyWatcher::wait_for_value`]: super::PropertyWatcher::wait_for_value
+pub fn set_observer(observer: Observer) {
+    *OBSERVER.write().unwrap_or_else(|e| e.into_inner()) = Some(observer);
+}
+
+/// Removes the observer set with [`set_observer`], if any.
+pub fn clear_observer() {
+    *OBSERVER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Reports an access to the observer, if any.
+pub(crate) fn notify(access: Access<'_>) {
+    // Call the observer without holding the lock, since it may access properties itself.
+    let observer = OBSERVER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(observer) = observer {
+        observer(access);
+    }
+}
+
+#[cfg(all(test, feature = "test-mock"))]
+mod test {
+    use super::super::{mock, read, write, PropertyWatcher};
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    // The observer is global, so the tests that set it must not run concurrently.
+    static OBSERVER_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn observer_test() {
+        static ACCESSES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let _guard = OBSERVER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Other tests may access properties concurrently, so only record this test's property.
+        set_observer(Arc::new(|access| {
+            let (kind, name) = match access {
+                Access::Read { name } => ("read", name),
+                Access::Write { name } => ("write", name),
+                Access::Wait { name } => ("wait", name),
+            };
+            if name == "observer.test" {
+                ACCESSES.lock().unwrap().push(kind.to_owned());
+            }
+        }));
+
+        mock::set("observer.test", "1");
+        read("observer.test").unwrap();
+        write("observer.test", "2").unwrap();
+        let mut watcher = PropertyWatcher::new("observer.test").unwrap();
+        watcher.wait_for_value("2", Some(Duration::from_secs(10))).unwrap();
+        clear_observer();
+        read("observer.test").unwrap();
+
+        assert_eq!(*ACCESSES.lock().unwrap(), ["read", "write", "wait", "read"]);
+    }
+
+    #[test]
+    fn reentrant_observer_test() {
+        static TAGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let _guard = OBSERVER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Tag each access with another property, like telemetry tagging events with a build
+        // property. The tag's own read is reported too, but doesn't read anything further.
+        fn tag_observer(access: Access<'_>) {
+            if let Access::Read { name: "observer.test.reentrant" } = access {
+                let tag = read("observer.test.reentrant.tag").unwrap().unwrap();
+                TAGS.lock().unwrap().push(tag);
+            }
+        }
+
+        mock::set("observer.test.reentrant", "1");
+        mock::set("observer.test.reentrant.tag", "tag");
+        set_observer(Arc::new(tag_observer));
+        read("observer.test.reentrant").unwrap();
+        assert_eq!(*TAGS.lock().unwrap(), ["tag"]);
+
+        // Replacing the observer while it runs must not deadlock with the re-entrant read.
+        let handle = std::thread::spawn(|| {
+            for _ in 0..100 {
+                clear_observer();
+                set_observer(Arc::new(tag_observer));
+            }
+        });
+        for _ in 0..100 {
+            read("observer.test.reentrant").unwrap();
+        }
+        handle.join().unwrap();
+        clear_observer();
+        assert!(TAGS.lock().unwrap().iter().all(|tag| tag == "tag"));
+    }
+}
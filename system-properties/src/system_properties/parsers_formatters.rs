@@ -2,8 +2,10 @@
 //!
 //! These functions should only be used in the system properties generated code.
 
+use std::fmt;
 use std::str::FromStr;
 use std::string::ToString;
+use std::time::Duration;
 
 type Result<T> = std::result::Result<T, String>;
 
@@ -15,6 +17,73 @@ pub fn parse<T: FromStr>(s: &str) -> Result<T> {
         .map_err(|_| format!("Can't convert '{}' to '{}'.", s, std::any::type_name::<T>()))
 }
 
+/// Adds the name of the property whose value couldn't be parsed to an error returned by one of
+/// the parsers.
+pub fn with_property_name(name: &str, error: String) -> String {
+    format!("Invalid value for '{}': {}", name, error)
+}
+
+/// Parses the value of the property `name` as a `T`, or returns an error including both the name
+/// and the value.
+pub fn parse_named<T: FromStr>(name: &str, s: &str) -> Result<T> {
+    parse(s).map_err(|e| with_property_name(name, e))
+}
+
+/// Integer types that can be parsed in an arbitrary radix.
+pub trait FromStrRadix: Sized {
+    /// Parses `s` as an integer in the given radix. See [`i32::from_str_radix`].
+    fn from_str_radix(s: &str, radix: u32) -> std::result::Result<Self, std::num::ParseIntError>;
+}
+
+macro_rules! impl_from_str_radix {
+    ($($t:ty)*) => {
+        $(
+            impl FromStrRadix for $t {
+                fn from_str_radix(
+                    s: &str,
+                    radix: u32,
+                ) -> std::result::Result<Self, std::num::ParseIntError> {
+                    <$t>::from_str_radix(s, radix)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_str_radix!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+/// Parses the given string as an integer, detecting the radix from its prefix, or returns an
+/// error including the string value.
+///
+/// `0x`, `0o`, and `0b` (or their uppercase forms) select hexadecimal, octal, and binary. Values
+/// without a prefix are decimal. The prefix may be preceded by a sign, as in `-0x10`.
+pub fn parse_int_auto<T: FromStrRadix>(s: &str) -> Result<T> {
+    let error = || format!("Can't convert '{}' to '{}'.", s, std::any::type_name::<T>());
+
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (radix, digits) = match unsigned.get(..2) {
+        Some("0x" | "0X") => (16, &unsigned[2..]),
+        Some("0o" | "0O") => (8, &unsigned[2..]),
+        Some("0b" | "0B") => (2, &unsigned[2..]),
+        _ => (10, unsigned),
+    };
+    // from_str_radix accepts its own sign, which must not appear after the prefix.
+    if digits.starts_with(['+', '-']) {
+        return Err(error());
+    }
+
+    let result = if negative {
+        T::from_str_radix(&format!("-{digits}"), radix)
+    } else {
+        T::from_str_radix(digits, radix)
+    };
+    result.map_err(|_| error())
+}
+
 /// Parses the given string as a boolean or returns an error message including the string.
 ///
 /// `true` and `1` are both considered true, `false` and `0` are false. Any other value is invalid.
@@ -26,52 +95,210 @@ pub fn parse_bool(s: &str) -> Result<bool> {
     }
 }
 
-fn parse_list_with<T, F>(s: &str, f: F) -> Result<Vec<T>>
+/// Parses the given string as a boolean the way [`read_bool`](super::read_bool) does, or returns
+/// an error message including the string.
+///
+/// Any of [`BOOL_TRUE_TOKENS`](super::BOOL_TRUE_TOKENS) or
+/// [`BOOL_FALSE_TOKENS`](super::BOOL_FALSE_TOKENS) is accepted, ignoring ASCII case and
+/// surrounding whitespace. This is a superset of what [`parse_bool`] accepts.
+pub fn parse_bool_lenient(s: &str) -> Result<bool> {
+    let value = s.trim();
+    let matches = |tokens: &[&str]| tokens.iter().any(|t| t.eq_ignore_ascii_case(value));
+    if matches(super::BOOL_TRUE_TOKENS) {
+        Ok(true)
+    } else if matches(super::BOOL_FALSE_TOKENS) {
+        Ok(false)
+    } else {
+        Err(format!("Can't convert '{}' to 'bool'.", s))
+    }
+}
+
+/// Parses the given string as one of the values in `table`, which maps each valid string to its
+/// value. If the string isn't in the table, returns an error listing the valid strings.
+pub fn parse_enum<T: Clone>(s: &str, table: &[(&str, T)]) -> Result<T> {
+    match table.iter().find(|(name, _)| *name == s) {
+        Some((_, value)) => Ok(value.clone()),
+        None => {
+            let names = table.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            Err(format!(
+                "Can't convert '{}' to '{}'. Valid values are: {}.",
+                s,
+                std::any::type_name::<T>(),
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+/// A dotted version number, like the one in `ro.build.version.release`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// The first component.
+    pub major: u32,
+    /// The second component, or 0 if absent.
+    pub minor: u32,
+    /// The third component, or 0 if absent.
+    pub patch: u32,
+}
+
+/// Formats the version as `major.minor.patch`, which [`parse_version`] parses back.
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Parses the given string as a dotted version number, or returns an error including the string.
+///
+/// Up to three `.`-separated decimal components are parsed, so `14`, `14.0`, and `14.0.1` are all
+/// valid, with missing components being 0. Parsing stops at the first character that isn't part of
+/// a component, so trailing junk is ignored: `14.0.1-rc1`, `14.0.1.2`, and `14.beta` are parsed as
+/// `14.0.1`, `14.0.1`, and `14.0.0`. The value must start with a digit and each component must fit
+/// in a `u32`.
+pub fn parse_version(s: &str) -> Result<Version> {
+    let error = || format!("Can't convert '{}' to 'Version'.", s);
+
+    let mut components = [0u32; 3];
+    let mut rest = s;
+    for (i, component) in components.iter_mut().enumerate() {
+        if i > 0 {
+            match rest.strip_prefix('.') {
+                Some(r) if r.starts_with(|c: char| c.is_ascii_digit()) => rest = r,
+                _ => break,
+            }
+        }
+        let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(error());
+        }
+        *component = rest[..len].parse().map_err(|_| error())?;
+        rest = &rest[len..];
+    }
+
+    let [major, minor, patch] = components;
+    Ok(Version { major, minor, patch })
+}
+
+/// Parses the given string as a duration, or returns an error including the string.
+///
+/// The value is a non-negative decimal integer, optionally followed by a unit suffix: `ms` for
+/// milliseconds, `s` for seconds, or `m` for minutes. A value without a suffix is in
+/// `default_unit`, so `parse_duration("500", Duration::from_millis(1))` is 500 milliseconds.
+/// Whitespace, signs, and fractions aren't accepted.
+pub fn parse_duration(s: &str, default_unit: Duration) -> Result<Duration> {
+    let error = || format!("Can't convert '{}' to 'Duration'.", s);
+
+    let (digits, unit) = if let Some(digits) = s.strip_suffix("ms") {
+        (digits, Duration::from_millis(1))
+    } else if let Some(digits) = s.strip_suffix('s') {
+        (digits, Duration::from_secs(1))
+    } else if let Some(digits) = s.strip_suffix('m') {
+        (digits, Duration::from_secs(60))
+    } else {
+        (s, default_unit)
+    };
+    // u64::from_str accepts a leading `+`.
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(error());
+    }
+    let count = digits.parse::<u64>().map_err(|_| error())?;
+
+    let nanos = unit.as_nanos().checked_mul(count.into()).ok_or_else(error)?;
+    let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| error())?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+fn parse_list_with<T, F>(s: &str, sep: char, escape: Option<char>, f: F) -> Result<Vec<T>>
 where
     F: Fn(&str) -> Result<T>,
 {
-    let mut result = Vec::new();
     if s.is_empty() {
-        return Ok(result);
+        return Ok(Vec::new());
     }
 
-    let mut chars = s.chars();
-    let mut current = chars.next();
-    while current.is_some() {
-        // Extract token.
-        let mut token = String::with_capacity(s.len());
-        while let Some(value) = current {
-            if value == ',' {
-                break;
-            }
-            if value == '\\' {
-                current = chars.next()
-            }
-            if let Some(value) = current {
-                token.push(value);
-            }
-            current = chars.next();
+    // Escaped separators are counted too, so this may overestimate, but never underestimates.
+    let mut result = Vec::with_capacity(s.matches(sep).count() + 1);
+    // Every separator ends a token, so a leading, trailing, or repeated separator produces an
+    // empty token. Tokens without escapes are borrowed from `s`. Only tokens containing an escape
+    // are copied into `unescaped`, which is sized to extend up to the next separator.
+    let mut start = 0;
+    let mut unescaped: Option<String> = None;
+    let mut chars = s.char_indices();
+    while let Some((i, value)) = chars.next() {
+        if value == sep {
+            result.push(f(unescaped.take().as_deref().unwrap_or(&s[start..i]))?);
+            start = i + sep.len_utf8();
+            continue;
+        }
+        if Some(value) == escape {
+            let token = unescaped.get_or_insert_with(|| {
+                let end = s[i..].find(sep).map_or(s.len(), |n| i + n);
+                let mut token = String::with_capacity(end - start);
+                token.push_str(&s[start..i]);
+                token
+            });
+            // An escape must be followed by the character it escapes. A trailing one can't have
+            // been produced by `format_list_with`, so it's an error rather than silently dropped.
+            let Some((_, value)) = chars.next() else {
+                return Err(format!("Can't parse '{}' as a list: dangling escape at the end.", s));
+            };
+            token.push(value);
+            continue;
+        }
+        if let Some(token) = &mut unescaped {
+            token.push(value);
         }
-        // Parse token.
-        result.push(f(token.as_str())?);
-        current = chars.next()
     }
+    result.push(f(unescaped.as_deref().unwrap_or(&s[start..]))?);
 
     Ok(result)
 }
 
 /// Parses the given string as a comma-separated list of `T`s.
 ///
-/// Literal commas can be escaped with `\`.
+/// Literal commas can be escaped with `\`. An empty string is an empty list. Otherwise, every
+/// comma separates two elements, so `"a,"` is `["a", ""]` and `"a,,b"` is `["a", "", "b"]`. A
+/// literal backslash must be escaped as `\\`. A lone `\` at the end of the string is an error.
 pub fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>> {
-    parse_list_with(s, parse)
+    parse_list_with_sep(s, ',')
+}
+
+/// Parses the given string as a list of `T`s separated by `sep`.
+///
+/// Literal separators can be escaped with `\`.
+pub fn parse_list_with_sep<T: FromStr>(s: &str, sep: char) -> Result<Vec<T>> {
+    parse_list_with(s, sep, Some('\\'), parse)
+}
+
+/// Parses the given string as a list of `T`s separated by `sep`, with `escape` as the escape
+/// character.
+///
+/// Literal separators and escape characters can be escaped with `escape`. An unescaped `escape` at
+/// the end of the string is an error. If `escape` is `None`, there is no escaping, so every `sep`
+/// is a separator and every other character, including `\`, is literal.
+pub fn parse_list_with_escape<T: FromStr>(
+    s: &str,
+    sep: char,
+    escape: Option<char>,
+) -> Result<Vec<T>> {
+    parse_list_with(s, sep, escape, parse)
 }
 
 /// Parses the given string as a comma-separated list of booleans.
 ///
-/// Literal commas can be escaped with `\`.
+/// Literal commas can be escaped with `\`. Elements are parsed with [`parse_bool`], so this
+/// parses the output of both [`format_bool_list`] and [`format_bool_list_as_int`], as well as
+/// lists mixing the two forms.
 pub fn parse_bool_list(s: &str) -> Result<Vec<bool>> {
-    parse_list_with(s, parse_bool)
+    parse_list_with(s, ',', Some('\\'), parse_bool)
+}
+
+/// Parses the given string as a comma-separated list of booleans, accepting every form that
+/// [`parse_bool_lenient`] does, like `yes` or `off`.
+///
+/// Literal commas can be escaped with `\`. This parses everything [`parse_bool_list`] does.
+pub fn parse_bool_list_lenient(s: &str) -> Result<Vec<bool>> {
+    parse_list_with(s, ',', Some('\\'), parse_bool_lenient)
 }
 
 // Formatters.
@@ -97,31 +324,489 @@ pub fn format_bool_as_int(v: &bool) -> String {
     "0".into()
 }
 
-fn format_list_with<T, F>(v: &[T], f: F) -> String
+/// Converts the given value to the string it's mapped to in `table`, or returns an error if the
+/// value isn't in the table.
+pub fn format_enum<T: PartialEq>(v: &T, table: &[(&str, T)]) -> Result<String> {
+    table
+        .iter()
+        .find(|(_, value)| value == v)
+        .map(|(name, _)| (*name).to_owned())
+        .ok_or_else(|| format!("Value of '{}' is not in the table.", std::any::type_name::<T>()))
+}
+
+/// Converts the given duration to a string that [`parse_duration`] parses back.
+///
+/// The largest of minutes, seconds, or milliseconds that represents the duration exactly is used,
+/// eg. `2m`, `90s`, or `1500ms`. Precision below a millisecond is truncated.
+pub fn format_duration(v: &Duration) -> String {
+    let millis = v.as_millis();
+    if millis != 0 && millis.is_multiple_of(60_000) {
+        format!("{}m", millis / 60_000)
+    } else if millis.is_multiple_of(1_000) {
+        format!("{}s", millis / 1_000)
+    } else {
+        format!("{}ms", millis)
+    }
+}
+
+fn format_list_with<T, F>(v: &[T], sep: char, f: F) -> String
 where
     F: Fn(&T) -> String,
 {
-    let mut result = String::new();
-    for item in v {
-        let formatted = f(item);
-        result.push_str(formatted.as_str());
-        result.push(',');
+    // Format everything up front so that the result can be allocated once. Only escapes, which
+    // are rare, can make it grow after that.
+    let items = v.iter().map(f).collect::<Vec<_>>();
+    let len = items.iter().map(String::len).sum::<usize>()
+        + items.len().saturating_sub(1) * sep.len_utf8();
+
+    // Like `join`, separators only go between elements. This means that an empty list and a list
+    // with a single empty element are both formatted as an empty string, which parses back as an
+    // empty list.
+    let mut result = String::with_capacity(len);
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            result.push(sep);
+        }
+        // Escape so that the list can be parsed back by `parse_list_with`.
+        for c in item.chars() {
+            if c == sep || c == '\\' {
+                result.push('\\');
+            }
+            result.push(c);
+        }
     }
-    result.pop();
     result
 }
 
 /// Converts the given list of values to a string, separated by commas.
+///
+/// Literal commas and backslashes in the values are escaped with `\`. An empty list is formatted
+/// as an empty string. So is a list with a single empty element, which is the one list that
+/// doesn't survive a round trip through [`parse_list`], since it's parsed back as an empty list.
 pub fn format_list<T: ToString>(v: &[T]) -> String {
-    format_list_with(v, format)
+    format_list_with_sep(v, ',')
+}
+
+/// Converts the given list of values to a string, separated by `sep`.
+///
+/// Literal separators and backslashes in the values are escaped with `\`.
+pub fn format_list_with_sep<T: ToString>(v: &[T], sep: char) -> String {
+    format_list_with(v, sep, format)
 }
 
 /// Converts the given list of booleans to a string, separated by commas.
+///
+/// The result can be parsed back with [`parse_bool_list`].
 pub fn format_bool_list(v: &[bool]) -> String {
-    format_list_with(v, format_bool)
+    format_list_with(v, ',', format_bool)
 }
 
 /// Converts the given list of booleans to a string of `0`s and `1`s separated by commas.
+///
+/// The result can be parsed back with [`parse_bool_list`].
 pub fn format_bool_list_as_int(v: &[bool]) -> String {
-    format_list_with(v, format_bool_as_int)
+    format_list_with(v, ',', format_bool_as_int)
+}
+
+// Codecs.
+
+/// A type that can be stored in a system property.
+///
+/// For every value `v`, `T::parse(&v.format())` returns `v`, with two exceptions. A list
+/// containing only an empty element is formatted the same way as an empty list. Floating-point NaN
+/// is formatted as `NaN` regardless of its sign and payload, so it parses back as a positive NaN,
+/// which doesn't compare equal to the original since no NaN does.
+pub trait PropCodec: Sized {
+    /// Parses the given property value, or returns an error including the string value.
+    fn parse(s: &str) -> Result<Self>;
+
+    /// Converts the value to a property value.
+    fn format(&self) -> String;
+}
+
+macro_rules! impl_prop_codec {
+    ($($t:ty)*) => {
+        $(
+            impl PropCodec for $t {
+                fn parse(s: &str) -> Result<Self> {
+                    parse(s)
+                }
+
+                fn format(&self) -> String {
+                    format(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_prop_codec!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize f32 f64 char String);
+
+/// Booleans are formatted as `true` or `false`. Both those and `1` or `0` are parsed.
+impl PropCodec for bool {
+    fn parse(s: &str) -> Result<Self> {
+        parse_bool(s)
+    }
+
+    fn format(&self) -> String {
+        format_bool(self)
+    }
+}
+
+/// A boolean that is formatted as `1` or `0`. Both those and `true` or `false` are parsed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BoolAsInt(pub bool);
+
+impl PropCodec for BoolAsInt {
+    fn parse(s: &str) -> Result<Self> {
+        parse_bool(s).map(Self)
+    }
+
+    fn format(&self) -> String {
+        format_bool_as_int(&self.0)
+    }
+}
+
+/// Lists are separated by commas, with literal commas and backslashes escaped with `\`.
+impl<T: PropCodec> PropCodec for Vec<T> {
+    fn parse(s: &str) -> Result<Self> {
+        parse_list_with(s, ',', Some('\\'), T::parse)
+    }
+
+    fn format(&self) -> String {
+        format_list_with(self, ',', T::format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_round_trip<T: PropCodec + PartialEq + std::fmt::Debug>(values: &[T]) {
+        for v in values {
+            assert_eq!(&T::parse(&v.format()).unwrap(), v, "testing with {:?}", v);
+        }
+    }
+
+    #[test]
+    fn codec_round_trip_test() {
+        assert_round_trip(&[0u8, 1, u8::MAX]);
+        assert_round_trip(&[i64::MIN, -1, 0, i64::MAX]);
+        assert_round_trip(&[0.0f64, -1.5, 0.1, f64::MAX, f64::MIN_POSITIVE]);
+        assert_round_trip(&['a', ',', '\\']);
+        assert_round_trip(&[String::new(), "a,b\\c".to_owned()]);
+        assert_round_trip(&[true, false]);
+        assert_round_trip(&[BoolAsInt(true), BoolAsInt(false)]);
+        assert_round_trip(&[vec![], vec![1u32], vec![1, 2, 3]]);
+        assert_round_trip(&[
+            vec![],
+            vec!["a,b".to_owned(), "\\".to_owned()],
+            vec![String::new(), String::new()],
+            vec!["a".to_owned(), String::new()],
+        ]);
+        assert_round_trip(&[vec![true, false], vec![]]);
+        assert_round_trip(&[vec![BoolAsInt(true), BoolAsInt(false)]]);
+
+        // NaN only round-trips as some NaN.
+        for nan in [f64::NAN, -f64::NAN] {
+            let parsed = f64::parse(&nan.format()).unwrap();
+            assert!(parsed.is_nan() && parsed.is_sign_positive(), "testing with {}", nan);
+        }
+    }
+
+    #[test]
+    fn codec_format_test() {
+        assert_eq!(true.format(), "true");
+        assert_eq!(BoolAsInt(true).format(), "1");
+        assert_eq!(<bool as PropCodec>::parse("1"), Ok(true));
+        assert_eq!(BoolAsInt::parse("false"), Ok(BoolAsInt(false)));
+        assert_eq!(vec!["a,b".to_owned(), "c".to_owned()].format(), "a\\,b,c");
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum State {
+        Idle,
+        Running,
+        Stopped,
+    }
+
+    const STATES: &[(&str, State)] =
+        &[("idle", State::Idle), ("running", State::Running), ("stopped", State::Stopped)];
+
+    #[test]
+    fn enum_test() {
+        for (name, state) in STATES {
+            assert_eq!(parse_enum(name, STATES).as_ref(), Ok(state), "testing with {}", name);
+            assert_eq!(format_enum(state, STATES).as_deref(), Ok(*name), "testing with {}", name);
+        }
+
+        let error = parse_enum("paused", STATES).unwrap_err();
+        assert!(error.contains("'paused'"), "testing with {}", error);
+        assert!(
+            error.ends_with("Valid values are: idle, running, stopped."),
+            "testing with {}",
+            error
+        );
+        assert!(format_enum(&State::Idle, &STATES[1..]).is_err());
+    }
+
+    #[test]
+    fn bool_list_test() {
+        let expected = [true, true, false, false];
+        assert_eq!(parse_bool_list("1,true,0,false").unwrap(), expected);
+        assert_eq!(parse_bool_list_lenient("1,true,0,false").unwrap(), expected);
+        assert_eq!(parse_bool_list_lenient("yes, ON ,n,Off").unwrap(), expected);
+        assert!(parse_bool_list("yes,no").is_err());
+        assert!(parse_bool_list_lenient("1,maybe").is_err());
+
+        let values = [true, false, true];
+        for formatted in [format_bool_list(&values), format_bool_list_as_int(&values)] {
+            assert_eq!(parse_bool_list(&formatted).unwrap(), values, "testing with {}", formatted);
+            assert_eq!(
+                parse_bool_list_lenient(&formatted).unwrap(),
+                values,
+                "testing with {}",
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn parse_named_test() {
+        assert_eq!(parse_named::<u32>("a.b", "1"), Ok(1));
+        assert_eq!(
+            parse_named::<u32>("a.b", "x").unwrap_err(),
+            "Invalid value for 'a.b': Can't convert 'x' to 'u32'."
+        );
+    }
+
+    #[test]
+    fn parse_version_test() {
+        for (s, expected) in [
+            ("14", (14, 0, 0)),
+            ("14.0", (14, 0, 0)),
+            ("14.0.1", (14, 0, 1)),
+            ("4.4.4", (4, 4, 4)),
+            ("14.0.1.2", (14, 0, 1)),
+            ("14.0.1-rc1", (14, 0, 1)),
+            ("14 beta", (14, 0, 0)),
+            ("14.", (14, 0, 0)),
+            ("14.beta", (14, 0, 0)),
+            ("14.1.x", (14, 1, 0)),
+            ("007.01", (7, 1, 0)),
+        ] {
+            let (major, minor, patch) = expected;
+            assert_eq!(parse_version(s), Ok(Version { major, minor, patch }), "testing with {}", s);
+        }
+        for s in ["", "beta", ".14", "-1", "+14", " 14", "99999999999"] {
+            assert!(parse_version(s).is_err(), "testing with {}", s);
+        }
+
+        let version = Version { major: 14, minor: 0, patch: 1 };
+        assert_eq!(version.to_string(), "14.0.1");
+        assert_eq!(parse_version(&version.to_string()), Ok(version));
+        assert!(parse_version("9").unwrap() < parse_version("10").unwrap());
+        assert!(parse_version("14.1").unwrap() > parse_version("14.0.9").unwrap());
+    }
+
+    #[test]
+    fn parse_duration_test() {
+        let ms = Duration::from_millis(1);
+        for (s, expected) in [
+            ("5000", Duration::from_millis(5000)),
+            ("0", Duration::ZERO),
+            ("250ms", Duration::from_millis(250)),
+            ("5s", Duration::from_secs(5)),
+            ("2m", Duration::from_secs(120)),
+            ("007s", Duration::from_secs(7)),
+        ] {
+            assert_eq!(parse_duration(s, ms), Ok(expected), "testing with {}", s);
+        }
+        assert_eq!(parse_duration("5", Duration::from_secs(1)), Ok(Duration::from_secs(5)));
+        for s in ["", "ms", "s", "-5", "+5", " 5", "5 s", "1.5s", "5h", "5sec", "5S", "0x10"] {
+            assert!(parse_duration(s, ms).is_err(), "testing with {}", s);
+        }
+        assert!(parse_duration(&format!("{}m", u64::MAX), ms).is_err());
+        assert_eq!(
+            parse_duration(&u64::MAX.to_string(), Duration::from_nanos(1)),
+            Ok(Duration::from_nanos(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn format_duration_test() {
+        for (v, expected) in [
+            (Duration::ZERO, "0s"),
+            (Duration::from_millis(1500), "1500ms"),
+            (Duration::from_secs(90), "90s"),
+            (Duration::from_secs(120), "2m"),
+            (Duration::from_micros(2500), "2ms"),
+        ] {
+            assert_eq!(format_duration(&v), expected, "testing with {:?}", v);
+        }
+        for v in [Duration::ZERO, Duration::from_millis(1), Duration::from_secs(3600)] {
+            assert_eq!(
+                parse_duration(&format_duration(&v), Duration::ZERO),
+                Ok(v),
+                "testing with {:?}",
+                v
+            );
+        }
+    }
+
+    #[test]
+    fn parse_f64_test() {
+        for (s, expected) in [
+            ("1.5", 1.5),
+            ("-0.25", -0.25),
+            ("+2", 2.0),
+            ("1e3", 1000.0),
+            (".5", 0.5),
+            ("5.", 5.0),
+            ("inf", f64::INFINITY),
+            ("-Infinity", f64::NEG_INFINITY),
+        ] {
+            assert_eq!(parse::<f64>(s), Ok(expected), "testing with {}", s);
+        }
+        assert!(parse::<f64>("nan").unwrap().is_nan());
+        assert!(parse::<f64>("NaN").unwrap().is_nan());
+        for s in ["", "1,5", " 1.5", "1.5 ", "0x10", "1.5f"] {
+            assert!(parse::<f64>(s).is_err(), "testing with {}", s);
+        }
+        for v in [f64::INFINITY, f64::NEG_INFINITY, 0.1, -1e300] {
+            assert_eq!(parse::<f64>(&format(&v)), Ok(v), "testing with {}", v);
+        }
+    }
+
+    #[test]
+    fn parse_f64_list_test() {
+        assert_eq!(parse_list::<f64>("1.5,-2,1e3").unwrap(), [1.5, -2.0, 1000.0]);
+        assert_eq!(parse_list::<f64>("").unwrap(), Vec::<f64>::new());
+        assert!(parse_list::<f64>("1.5,").is_err());
+        assert!(parse_list::<f64>("1.5, 2").is_err());
+        let values = [0.5, f64::INFINITY, -3.25];
+        assert_eq!(parse_list::<f64>(&format_list(&values)).unwrap(), values);
+    }
+
+    #[test]
+    fn parse_int_auto_test() {
+        assert_eq!(parse_int_auto::<u32>("0xFF"), Ok(255));
+        assert_eq!(parse_int_auto::<u32>("0Xff"), Ok(255));
+        assert_eq!(parse_int_auto::<u32>("0o17"), Ok(15));
+        assert_eq!(parse_int_auto::<u8>("0b101"), Ok(5));
+        assert_eq!(parse_int_auto::<u32>("1234"), Ok(1234));
+        assert_eq!(parse_int_auto::<u32>("0"), Ok(0));
+        assert_eq!(parse_int_auto::<i32>("-0x10"), Ok(-16));
+        assert_eq!(parse_int_auto::<i32>("+42"), Ok(42));
+        assert!(parse_int_auto::<u32>("0x").is_err());
+        assert!(parse_int_auto::<i32>("0x-1").is_err());
+        assert!(parse_int_auto::<u8>("0x100").is_err());
+        assert!(parse_int_auto::<u32>("0xZZ").is_err());
+    }
+
+    #[test]
+    fn parse_list_with_sep_test() {
+        assert_eq!(parse_list_with_sep::<String>("a:b\\:c:d", ':').unwrap(), ["a", "b:c", "d"]);
+        assert_eq!(parse_list_with_sep::<String>("a,b:c", ':').unwrap(), ["a,b", "c"]);
+        assert_eq!(parse_list_with_sep::<u32>("1 2 3", ' ').unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_list_with_escape_test() {
+        assert_eq!(
+            parse_list_with_escape::<String>("a\\b,c\\,d", ',', None).unwrap(),
+            ["a\\b", "c\\", "d"]
+        );
+        assert_eq!(parse_list_with_escape::<String>("a\\", ',', None).unwrap(), ["a\\"]);
+        assert!(parse_list_with_escape::<String>("a,b^", ',', Some('^')).is_err());
+        assert_eq!(
+            parse_list_with_escape::<String>("a^,b,c^^", ',', Some('^')).unwrap(),
+            ["a,b", "c^"]
+        );
+        assert_eq!(
+            parse_list_with_escape::<String>("a\\,b", ',', Some('\\')).unwrap(),
+            parse_list::<String>("a\\,b").unwrap()
+        );
+    }
+
+    #[test]
+    fn format_list_with_sep_test() {
+        assert_eq!(format_list_with_sep(&[1, 2, 3], ':'), "1:2:3");
+        assert_eq!(format_list_with_sep::<u32>(&[], ':'), "");
+        assert_eq!(format_list_with_sep(&["a:b", "c\\"], ':'), "a\\:b:c\\\\");
+    }
+
+    #[test]
+    fn parse_list_empty_elements_test() {
+        assert_eq!(parse_list::<String>("").unwrap(), Vec::<String>::new());
+        assert_eq!(parse_list::<String>("a,b,").unwrap(), ["a", "b", ""]);
+        assert_eq!(parse_list::<String>(",a").unwrap(), ["", "a"]);
+        assert_eq!(parse_list::<String>("a,,b").unwrap(), ["a", "", "b"]);
+        assert_eq!(parse_list::<String>(",").unwrap(), ["", ""]);
+    }
+
+    #[test]
+    fn parse_list_trailing_escape_test() {
+        // An escaped backslash is a literal backslash.
+        assert_eq!(parse_list::<String>("a\\\\").unwrap(), ["a\\"]);
+        assert_eq!(parse_list::<String>("a\\\\,b").unwrap(), ["a\\", "b"]);
+        // A lone backslash at the end has nothing to escape.
+        for s in ["a\\", "\\", "a,b\\", "a\\\\\\"] {
+            let error = parse_list::<String>(s).unwrap_err();
+            assert!(error.contains("dangling escape"), "testing with {}", s);
+        }
+        assert!(parse_bool_list("1,0\\").is_err());
+    }
+
+    #[test]
+    fn list_empty_edge_cases_test() {
+        assert_eq!(format_list::<String>(&[]), "");
+        assert_eq!(parse_list::<String>("").unwrap(), Vec::<String>::new());
+        // A single empty element is indistinguishable from an empty list.
+        assert_eq!(format_list(&[""]), "");
+        // Two empty elements are not.
+        assert_eq!(format_list(&["", ""]), ",");
+        assert_eq!(parse_list::<String>(",").unwrap(), ["", ""]);
+    }
+
+    #[test]
+    fn list_all_empty_elements_test() {
+        // Separators only go between elements, so every element but the first adds exactly one.
+        for n in 2..=5 {
+            let v = vec![String::new(); n];
+            let formatted = format_list(&v);
+            assert_eq!(formatted, ",".repeat(n - 1), "testing with {}", n);
+            assert_eq!(parse_list::<String>(&formatted).unwrap(), v, "testing with {}", n);
+        }
+        assert_eq!(format_list_with(&[1, 2, 3], ';', |_| String::new()), ";;");
+    }
+
+    #[test]
+    fn list_round_trip_test() {
+        let cases: &[&[&str]] = &[
+            &["a", "b"],
+            &["a,b", "c"],
+            &["\\", "a\\,b", "\\\\", ",,"],
+            &["", "a"],
+            &["a", "", "", "b"],
+            &["a", ""],
+            &["", ""],
+        ];
+        for case in cases {
+            let v: Vec<String> = case.iter().map(|s| s.to_string()).collect();
+            assert_eq!(parse_list::<String>(&format_list(&v)).unwrap(), v, "testing with {:?}", v);
+        }
+    }
+
+    #[test]
+    fn large_list_test() {
+        let values = (0..10_000u32).collect::<Vec<_>>();
+        let formatted = format_list(&values);
+        // Without escapes, the length is computed exactly.
+        assert_eq!(formatted.capacity(), formatted.len());
+        assert_eq!(parse_list::<u32>(&formatted).unwrap(), values);
+
+        let values = (0..10_000).map(|i| format!("{i},\\{i}")).collect::<Vec<_>>();
+        assert_eq!(parse_list::<String>(&format_list(&values)).unwrap(), values);
+    }
 }
@@ -0,0 +1,131 @@
+//! Cache for read-only system properties.
+//!
+//! Properties whose names start with `ro.` can only be set once, so after a value has been read,
+//! it can be kept for the lifetime of the process. [`get_ro`] reads each such property at most
+//! once and hands out the cached value afterwards, without any further FFI calls.
+//!
+//! [`read_ro_checked`] instead reads the property every time and checks that it still has the
+//! first value that was observed, for code that relies on `ro.` properties being immutable.
+
+use super::{is_read_only, read, PropertyWatcherError, Result};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// The values are leaked so that references to them can be handed out without holding the lock.
+// Only properties that exist are cached, so the number of leaked values is bounded by the
+// number of read-only properties.
+static CACHE: Mutex<BTreeMap<String, &'static str>> = Mutex::new(BTreeMap::new());
+
+// The first value observed by read_ro_checked for each property. This is separate from CACHE so
+// that get_ro and read_ro_checked don't affect each other.
+static FIRST_SEEN: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Reads a read-only system property, caching the value for subsequent calls.
+///
+/// Returns `Ok(None)` if the property doesn't exist. Absent properties aren't cached, since a
+/// read-only property can still be set once after it's first queried. Returns
+/// [`PropertyWatcherError::NotReadOnly`] if the name doesn't start with `ro.`, because caching a
+/// mutable property would return stale values.
+pub fn get_ro(name: &str) -> Result<Option<&'static str>> {
+    if !is_read_only(name) {
+        return Err(PropertyWatcherError::NotReadOnly(name.to_owned()));
+    }
+
+    if let Some(value) = CACHE.lock().unwrap_or_else(|e| e.into_inner()).get(name) {
+        return Ok(Some(value));
+    }
+
+    let Some(value) = read(name)? else {
+        return Ok(None);
+    };
+
+    // Another thread may have cached the value in the meantime. Keep the existing entry so that
+    // the same reference is always returned.
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let value = *cache.entry(name.to_owned()).or_insert_with(|| value.leak());
+    Ok(Some(value))
+}
+
+/// Reads a read-only system property and checks that it hasn't changed since it was first read
+/// by this function.
+///
+/// `ro.` properties can only be set once, but bugs or tools that modify the property area
+/// directly, like `resetprop`, can still change them. This returns
+/// [`PropertyWatcherError::ReadOnlyChanged`] if the value differs from the first one observed,
+/// which is remembered for the lifetime of the process. Only reads made through this function are
+/// checked. Returns `Ok(None)` if the property doesn't exist and has never been observed, and
+/// [`PropertyWatcherError::NotReadOnly`] if the name doesn't start with `ro.`.
+pub fn read_ro_checked(name: &str) -> Result<Option<String>> {
+    if !is_read_only(name) {
+        return Err(PropertyWatcherError::NotReadOnly(name.to_owned()));
+    }
+
+    let current = read(name)?;
+    let mut first_seen = FIRST_SEEN.lock().unwrap_or_else(|e| e.into_inner());
+    match (first_seen.get(name), current) {
+        (Some(first), current) if current.as_deref() != Some(first.as_str()) => {
+            Err(PropertyWatcherError::ReadOnlyChanged {
+                name: name.to_owned(),
+                first: first.clone(),
+                current,
+            })
+        }
+        (None, Some(current)) => {
+            first_seen.insert(name.to_owned(), current.clone());
+            Ok(Some(current))
+        }
+        (_, current) => Ok(current),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn not_read_only_test() {
+        assert!(matches!(
+            get_ro("persist.ro_cache.test"),
+            Err(PropertyWatcherError::NotReadOnly(name)) if name == "persist.ro_cache.test"
+        ));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn get_ro_test() {
+        use super::super::mock;
+
+        assert_eq!(get_ro("ro.ro_cache.test").unwrap(), None);
+        mock::set("ro.ro_cache.test", "1");
+        assert_eq!(get_ro("ro.ro_cache.test").unwrap(), Some("1"));
+        // The mock allows changing read-only properties, which shows that the value is cached.
+        mock::set("ro.ro_cache.test", "2");
+        assert_eq!(get_ro("ro.ro_cache.test").unwrap(), Some("1"));
+    }
+
+    #[cfg(feature = "test-mock")]
+    #[test]
+    fn read_ro_checked_test() {
+        use super::super::mock;
+
+        let name = "ro.ro_cache.test.checked";
+        assert_eq!(read_ro_checked(name).unwrap(), None);
+        mock::set(name, "1");
+        assert_eq!(read_ro_checked(name).unwrap().as_deref(), Some("1"));
+        assert_eq!(read_ro_checked(name).unwrap().as_deref(), Some("1"));
+
+        mock::set(name, "2");
+        assert!(matches!(
+            read_ro_checked(name),
+            Err(PropertyWatcherError::ReadOnlyChanged { first, current, .. })
+                if first == "1" && current.as_deref() == Some("2")
+        ));
+        // The first value is kept, so restoring it makes reads succeed again.
+        mock::set(name, "1");
+        assert_eq!(read_ro_checked(name).unwrap().as_deref(), Some("1"));
+        assert!(matches!(
+            read_ro_checked("persist.ro_cache.test.checked"),
+            Err(PropertyWatcherError::NotReadOnly(_))
+        ));
+    }
+}
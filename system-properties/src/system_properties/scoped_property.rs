@@ -0,0 +1,88 @@
+//! Temporarily changing a system property.
+
+use super::{read, try_write, write, Result};
+
+/// ScopedProperty sets a system property and restores its previous value when dropped, including
+/// when unwinding from a panic. This is mainly useful in tests that need to change a property
+/// without affecting later tests.
+///
+/// Properties can't be deleted, so if the property didn't exist before, it's set to an empty
+/// value on drop, which most readers treat the same as an absent property. Restoring is best
+/// effort: errors are ignored since they can't be reported from `drop`.
+#[must_use = "the previous value is restored as soon as the ScopedProperty is dropped"]
+pub struct ScopedProperty {
+    name: String,
+    // The value to restore on drop, or `None` if the guard was defused with `leak`.
+    old_value: Option<String>,
+}
+
+impl ScopedProperty {
+    /// Sets the system property `name` to `value`, returning a guard that restores the previous
+    /// value when dropped.
+    pub fn set(name: &str, value: &str) -> Result<Self> {
+        let old_value = read(name)?.unwrap_or_default();
+        write(name, value)?;
+        Ok(Self { name: name.to_owned(), old_value: Some(old_value) })
+    }
+
+    /// Returns the name of the property.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Consumes the guard without restoring the previous value, so that the new value persists.
+    pub fn leak(mut self) {
+        self.old_value = None;
+    }
+}
+
+impl Drop for ScopedProperty {
+    fn drop(&mut self) {
+        if let Some(old_value) = self.old_value.take() {
+            try_write(&self.name, &old_value);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-mock"))]
+mod test {
+    use super::super::mock;
+    use super::*;
+    use std::panic;
+
+    #[test]
+    fn restore_test() {
+        mock::set("scoped_property.test.restore", "old");
+        {
+            let guard = ScopedProperty::set("scoped_property.test.restore", "new").unwrap();
+            assert_eq!(guard.name(), "scoped_property.test.restore");
+            assert_eq!(read("scoped_property.test.restore").unwrap().as_deref(), Some("new"));
+        }
+        assert_eq!(read("scoped_property.test.restore").unwrap().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn absent_test() {
+        let guard = ScopedProperty::set("scoped_property.test.absent", "new").unwrap();
+        drop(guard);
+        assert_eq!(read("scoped_property.test.absent").unwrap().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn panic_test() {
+        mock::set("scoped_property.test.panic", "old");
+        let result = panic::catch_unwind(|| {
+            let _guard = ScopedProperty::set("scoped_property.test.panic", "new").unwrap();
+            panic!("testing");
+        });
+        assert!(result.is_err());
+        assert_eq!(read("scoped_property.test.panic").unwrap().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn leak_test() {
+        mock::set("scoped_property.test.leak", "old");
+        ScopedProperty::set("scoped_property.test.leak", "new").unwrap().leak();
+        assert_eq!(read("scoped_property.test.leak").unwrap().as_deref(), Some("new"));
+    }
+}
@@ -0,0 +1,129 @@
+//! Calling a function whenever a system property changes.
+
+use super::{PropertyWatcher, PropertyWatcherError, Result};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Calls `f` with the value of the system property `name` whenever it changes, on a background
+/// thread.
+///
+/// If the property exists, `f` is first called with its current value. Otherwise, the thread
+/// waits for the property to be created. `f` runs on the background thread, so a slow `f` delays
+/// noticing further changes, and changes that happen while `f` runs are coalesced into a single
+/// call with the latest value.
+///
+/// The thread runs until the returned [`Subscription`] is dropped or stopped, or until reading or
+/// waiting for the property fails. Returns an error if the [`PropertyWatcher`] can't be created
+/// or the thread can't be spawned.
+pub fn subscribe<F>(name: &str, f: F) -> Result<Subscription>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    let watcher = PropertyWatcher::new(name)?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let thread_cancel = cancel.clone();
+    let handle = thread::Builder::new()
+        .name(format!("subscribe {name}"))
+        .spawn(move || run(watcher, f, &thread_cancel))
+        .map_err(|_| PropertyWatcherError::WaitFailed { name: name.to_owned() })?;
+    Ok(Subscription { cancel, handle: Some(handle) })
+}
+
+fn run<F: FnMut(&str)>(mut watcher: PropertyWatcher, mut f: F, cancel: &AtomicBool) -> Result<()> {
+    let mut last_serial = None;
+    loop {
+        match watcher.wait_cancellable(None, cancel) {
+            Ok(()) => {}
+            Err(PropertyWatcherError::Cancelled { .. }) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        // A wait can return without a new value, eg. right after the property is created.
+        if let Some((value, serial)) = watcher.read_with_serial()? {
+            if last_serial != Some(serial) {
+                last_serial = Some(serial);
+                f(&value);
+            }
+        }
+    }
+}
+
+/// A background thread started by [`subscribe`].
+///
+/// Dropping the Subscription stops the thread and waits for it to exit. The thread checks
+/// whether it should stop every 100 ms while waiting, so dropping may block for that long, or
+/// longer if the callback is running. The callback is never called after the drop returns.
+pub struct Subscription {
+    cancel: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl Subscription {
+    /// Stops the thread and waits for it to exit, like dropping the Subscription. Returns the
+    /// error that stopped the thread early, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the callback panicked.
+    pub fn stop(mut self) -> Result<()> {
+        match self.join() {
+            Some(Ok(result)) => result,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => Ok(()),
+        }
+    }
+
+    fn join(&mut self) -> Option<thread::Result<Result<()>>> {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.handle.take().map(JoinHandle::join)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Errors and panics from the thread can only be reported by `stop`.
+        let _ = self.join();
+    }
+}
+
+#[cfg(all(test, feature = "test-mock"))]
+mod test {
+    use super::super::mock;
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn subscribe_test() {
+        mock::set("subscription.test.subscribe", "1");
+        let (sender, receiver) = mpsc::channel();
+        let subscription = subscribe("subscription.test.subscribe", move |value| {
+            sender.send(value.to_owned()).unwrap();
+        })
+        .unwrap();
+        let timeout = Duration::from_secs(10);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "1");
+
+        mock::set("subscription.test.subscribe", "2");
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "2");
+
+        subscription.stop().unwrap();
+        mock::set("subscription.test.subscribe", "3");
+        // The sender was dropped along with the thread.
+        assert!(matches!(receiver.recv(), Err(mpsc::RecvError)));
+    }
+
+    #[test]
+    fn subscribe_creation_test() {
+        let (sender, receiver) = mpsc::channel();
+        let subscription = subscribe("subscription.test.creation", move |value| {
+            sender.send(value.to_owned()).unwrap();
+        })
+        .unwrap();
+        mock::set("subscription.test.creation", "1");
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)).unwrap(), "1");
+        drop(subscription);
+        assert!(receiver.try_iter().next().is_none());
+    }
+}
@@ -0,0 +1,66 @@
+//! The `__system_property_*` functions used by this crate.
+//!
+//! Without the `test-mock` feature, these are bionic's functions. With it, every call is routed
+//! at runtime to either bionic or the mock store (see [`mock::enable`]). Cargo unifies features
+//! across the dependency graph, so a dependency enabling `test-mock` must not be able to silently
+//! replace the property area of a production build.
+
+pub use system_properties_bindgen::prop_info;
+#[cfg(not(feature = "test-mock"))]
+pub use system_properties_bindgen::{
+    __system_property_area_serial, __system_property_find, __system_property_foreach,
+    __system_property_read_callback, __system_property_serial, __system_property_set,
+    __system_property_wait,
+};
+
+#[cfg(feature = "test-mock")]
+pub use self::dispatch::*;
+
+#[cfg(feature = "test-mock")]
+mod dispatch {
+    use super::super::mock;
+    use super::prop_info;
+    use libc::timespec;
+    use std::ffi::{c_char, c_int, c_void};
+
+    macro_rules! dispatch {
+        ($(fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+            $(
+                pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                    if mock::is_active() {
+                        // SAFETY: The mock has the same contract as the bionic function.
+                        return unsafe { mock::ffi::$name($($arg),*) };
+                    }
+                    // Off-device, there's no bionic to call, so the mock is always active.
+                    #[cfg(target_os = "android")]
+                    // SAFETY: The caller upholds the contract of the bionic function.
+                    return unsafe { system_properties_bindgen::$name($($arg),*) };
+                    #[cfg(not(target_os = "android"))]
+                    unreachable!("the mock store is always used off-device")
+                }
+            )*
+        };
+    }
+
+    dispatch! {
+        fn __system_property_area_serial() -> u32;
+        fn __system_property_find(name: *const c_char) -> *const prop_info;
+        fn __system_property_read_callback(
+            pi: *const prop_info,
+            callback: Option<unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char, u32)>,
+            cookie: *mut c_void
+        );
+        fn __system_property_foreach(
+            callback: Option<unsafe extern "C" fn(*const prop_info, *mut c_void)>,
+            cookie: *mut c_void
+        ) -> c_int;
+        fn __system_property_serial(pi: *const prop_info) -> u32;
+        fn __system_property_set(name: *const c_char, value: *const c_char) -> c_int;
+        fn __system_property_wait(
+            pi: *const prop_info,
+            old_serial: u32,
+            new_serial_ptr: *mut u32,
+            relative_timeout: *const timespec
+        ) -> bool;
+    }
+}